@@ -0,0 +1,315 @@
+use error_stack::Report;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::error::{AppResult, CacheError, CacheResult, IntoAppResult};
+use crate::storage::git_blob_sha;
+use crate::GitBase;
+
+/// Content and blob SHA of a file as read from the storage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CachedFile {
+    pub content: String,
+    pub sha: String,
+    /// Version tag to revalidate the entry with, if the backend hands them out
+    pub etag: Option<String>,
+}
+
+/// Read counters of a [`CachedStorage`](crate::storage::CachedStorage)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Where a `(ref, path)` pointed when it was last read
+#[derive(Debug, Serialize, Deserialize)]
+struct PathEntry {
+    sha: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+}
+
+/// Files persisted below a directory so they outlive the process. Contents are stored once per
+/// blob SHA in `blobs/` and never go stale; `paths/` maps each `(ref, path)` to the SHA and ETag
+/// it was last read with, and is the only part that is invalidated.
+#[derive(Clone)]
+pub(crate) struct DiskCache {
+    root: PathBuf,
+}
+
+fn io_error(err: io::Error, path: &Path) -> Report<CacheError> {
+    Report::new(CacheError::Io(err.to_string()))
+        .attach_printable(format!("I/O failure on cache file: {}", path.display()))
+}
+
+impl DiskCache {
+    pub async fn open(root: impl Into<PathBuf>) -> CacheResult<Self> {
+        let cache = DiskCache { root: root.into() };
+        for dir in [cache.root.join("blobs"), cache.root.join("paths")] {
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .map_err(|e| io_error(e, &dir))?;
+        }
+
+        Ok(cache)
+    }
+
+    fn blob_path(&self, sha: &str) -> PathBuf {
+        let (dir, name) = sha.split_at(2.min(sha.len()));
+        self.root.join("blobs").join(dir).join(name)
+    }
+
+    /// Refs and paths may hold characters a file name cannot, so entries are named by a hash
+    fn entry_path(&self, reference: &str, path: &str) -> PathBuf {
+        let mut hasher = Sha1::new();
+        hasher.update(reference);
+        hasher.update("\0");
+        hasher.update(path);
+        self.root
+            .join("paths")
+            .join(format!("{:x}", hasher.finalize()))
+    }
+
+    /// The file `path` had on `reference` when it was last cached. Entries whose blob is missing
+    /// or does not hash to its SHA are treated as absent.
+    pub async fn get(&self, reference: &str, path: &str) -> CacheResult<Option<CachedFile>> {
+        let entry_path = self.entry_path(reference, path);
+        let Some(raw) = read_optional(&entry_path).await? else {
+            return Ok(None);
+        };
+        let Ok(entry) = serde_json::from_str::<PathEntry>(&raw) else {
+            return Ok(None);
+        };
+        let Some(content) = read_optional(&self.blob_path(&entry.sha)).await? else {
+            return Ok(None);
+        };
+        if git_blob_sha(content.as_bytes()) != entry.sha {
+            return Ok(None);
+        }
+
+        Ok(Some(CachedFile {
+            content,
+            sha: entry.sha,
+            etag: entry.etag,
+        }))
+    }
+
+    pub async fn insert(&self, reference: &str, path: &str, file: &CachedFile) -> CacheResult<()> {
+        let blob_path = self.blob_path(&file.sha);
+        if !tokio::fs::try_exists(&blob_path)
+            .await
+            .map_err(|e| io_error(e, &blob_path))?
+        {
+            write_atomic(&blob_path, &file.content).await?;
+        }
+
+        let entry = PathEntry {
+            sha: file.sha.clone(),
+            etag: file.etag.clone(),
+        };
+        let raw = serde_json::to_string(&entry)
+            .map_err(|e| Report::new(CacheError::InvalidFormat).attach_printable(e.to_string()))?;
+        write_atomic(&self.entry_path(reference, path), &raw).await
+    }
+
+    pub async fn invalidate(&self, reference: &str, path: &str) -> CacheResult<()> {
+        let entry_path = self.entry_path(reference, path);
+        match tokio::fs::remove_file(&entry_path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(e, &entry_path)),
+            _ => Ok(()),
+        }
+    }
+}
+
+async fn read_optional(path: &Path) -> CacheResult<Option<String>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(raw) => Ok(Some(raw)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        // Not UTF-8, so not something this cache wrote
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Ok(None),
+        Err(e) => Err(io_error(e, path)),
+    }
+}
+
+/// Distinguishes the temporary files of concurrent writes within this process
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Write through a temporary file and rename it into place, so that other writers sharing
+/// the directory never see a partial file
+async fn write_atomic(path: &Path, content: &str) -> CacheResult<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| io_error(e, dir))?;
+    }
+    let temp = path.with_extension(format!(
+        "tmp{}-{}",
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    tokio::fs::write(&temp, content)
+        .await
+        .map_err(|e| io_error(e, &temp))?;
+    tokio::fs::rename(&temp, path)
+        .await
+        .map_err(|e| io_error(e, path))
+}
+
+impl GitBase {
+    /// 文档缓存的命中与未命中次数
+    pub async fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// 启用目录 `dir` 下的磁盘缓存，读取过的文件在进程重启后仍可复用；
+    /// 多个进程可以共享同一目录
+    pub async fn enable_disk_cache(&self, dir: impl Into<PathBuf>) -> AppResult<()> {
+        self.cache
+            .enable_disk_cache(dir, &self.branch)
+            .await
+            .into_app()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::document_path;
+    use crate::storage::{InMemoryStorage, StorageBackend};
+    use serde_json::json;
+
+    /// Whether the document is cached, without counting a read
+    fn is_cached(gitbase: &GitBase, collection: &str, doc_id: &str) -> bool {
+        gitbase.cache.contains(&document_path(collection, doc_id))
+    }
+
+    /// Hits and misses since `before`
+    async fn reads_since(gitbase: &GitBase, before: CacheStats) -> CacheStats {
+        let now = gitbase.cache_stats().await;
+        CacheStats {
+            hits: now.hits - before.hits,
+            misses: now.misses - before.misses,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_document_cache() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Rust"}))
+            .await
+            .unwrap();
+
+        let before = gitbase.cache_stats().await;
+        gitbase.get_document("notes", &doc.id).await.unwrap();
+        let cached = gitbase.get_document("notes", &doc.id).await.unwrap();
+        assert_eq!(cached.meta.updated_sha, doc.meta.updated_sha);
+        assert_eq!(
+            reads_since(&gitbase, before).await,
+            CacheStats { hits: 1, misses: 1 }
+        );
+
+        // Served from the cache without touching the storage
+        let path = document_path("notes", &doc.id);
+        let raw = storage.read(&path).await.unwrap();
+        storage.write(&path, "not json").await.unwrap();
+        gitbase.get_document("notes", &doc.id).await.unwrap();
+        storage.write(&path, &raw).await.unwrap();
+
+        // Local writes invalidate the entry
+        gitbase
+            .update_document("notes", &doc.id, json!({"title": "Go"}))
+            .await
+            .unwrap();
+        assert!(!is_cached(&gitbase, "notes", &doc.id));
+        let before = gitbase.cache_stats().await;
+        let updated = gitbase.get_document("notes", &doc.id).await.unwrap();
+        assert_eq!(updated.content, json!({"title": "Go"}));
+        assert_eq!(
+            reads_since(&gitbase, before).await,
+            CacheStats { hits: 0, misses: 1 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.enable_disk_cache(dir.path()).await.unwrap();
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Rust"}))
+            .await
+            .unwrap();
+        gitbase.get_document("notes", &doc.id).await.unwrap();
+
+        // A new instance, as after a restart, is served from disk
+        let restarted = GitBase::with_storage(storage.clone());
+        restarted.enable_disk_cache(dir.path()).await.unwrap();
+        let cached = restarted.get_document("notes", &doc.id).await.unwrap();
+        assert_eq!(cached.meta.updated_sha, doc.meta.updated_sha);
+        assert_eq!(
+            restarted.cache_stats().await,
+            CacheStats { hits: 1, misses: 0 }
+        );
+
+        // Writes drop the path entry while the blob stays addressable by its SHA
+        restarted
+            .update_document("notes", &doc.id, json!({"title": "Go"}))
+            .await
+            .unwrap();
+        let disk = DiskCache::open(dir.path()).await.unwrap();
+        let path = document_path("notes", &doc.id);
+        assert_eq!(disk.get("main", &path).await.unwrap(), None);
+        assert!(disk.blob_path(&doc.meta.updated_sha).exists());
+
+        // Entries written by another process are checked against the storage
+        restarted.get_document("notes", &doc.id).await.unwrap();
+        let raw = storage.read(&path).await.unwrap();
+        storage
+            .write(&path, &raw.replace("Go", "Zig"))
+            .await
+            .unwrap();
+        let current = GitBase::with_storage(storage.clone());
+        current.enable_disk_cache(dir.path()).await.unwrap();
+        let document = current.get_document("notes", &doc.id).await.unwrap();
+        assert_eq!(document.content, json!({"title": "Zig"}));
+        assert_eq!(
+            current.cache_stats().await,
+            CacheStats { hits: 0, misses: 1 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_concurrent_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = DiskCache::open(dir.path()).await.unwrap();
+        let writes = (0..16).map(|i| {
+            let disk = disk.clone();
+            tokio::spawn(async move {
+                let content = format!("version {}", i % 2);
+                let file = CachedFile {
+                    sha: git_blob_sha(content.as_bytes()),
+                    content,
+                    etag: None,
+                };
+                disk.insert("main", "notes.json", &file).await
+            })
+        });
+        for write in writes.collect::<Vec<_>>() {
+            write.await.unwrap().unwrap();
+        }
+
+        let file = disk.get("main", "notes.json").await.unwrap().unwrap();
+        assert!(file.content.starts_with("version "));
+    }
+}
@@ -0,0 +1,446 @@
+use error_stack::Report;
+use serde::{Deserialize, Serialize};
+
+use crate::document::{collection_dir, COLLECTIONS_DIR, COLLECTION_META_FILE, GITKEEP_FILE};
+use crate::error::{AppResult, CollectionError, GBError, IntoAppResult, StorageError};
+use crate::index::{index_dir, Index};
+use crate::schema::schema_path;
+use crate::storage::{git_blob_sha, Change};
+use crate::{coder, GitBase};
+
+/// Contents of `collections/<name>/collection.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collection {
+    pub name: String,
+    pub collection_id: String,
+    pub created_at: String,
+    /// Version of the document shape, raised by [`GitBase::migrate`]
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+impl Collection {
+    fn new(name: &str) -> AppResult<Self> {
+        Ok(Collection {
+            name: name.to_string(),
+            collection_id: coder::generate_collection_id(name).into_app()?,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            schema_version: 0,
+        })
+    }
+
+    pub(crate) fn render(&self) -> AppResult<String> {
+        Ok(serde_json::to_string(self).map_err(GBError::from)?)
+    }
+}
+
+pub fn collection_meta_path(name: &str) -> String {
+    format!("{}/{}", collection_dir(name), COLLECTION_META_FILE)
+}
+
+/// A collection name must be usable as a single directory name
+fn validate_name(name: &str) -> AppResult<()> {
+    let valid = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\'])
+        && !name.starts_with('_');
+    if !valid {
+        return Err(Report::new(GBError::Collection(
+            CollectionError::InvalidName(name.to_string()),
+        )));
+    }
+
+    Ok(())
+}
+
+fn collection_error(err: CollectionError) -> Report<GBError> {
+    Report::new(GBError::Collection(err))
+}
+
+impl GitBase {
+    pub async fn create_collection(&self, name: &str) -> AppResult<Collection> {
+        validate_name(name)?;
+        self.ensure_database().await?;
+        if self.collection_exists(name).await? {
+            return Err(collection_error(CollectionError::AlreadyExists(
+                name.to_string(),
+            )));
+        }
+
+        let collection = Collection::new(name)?;
+        let mut tx = self.transaction();
+
+        // 1. 在集合目录下创建 `.gitkeep` 文件，让 Git 识别目录
+        let gitkeep_path = format!("{}/{}", collection_dir(name), GITKEEP_FILE);
+        tx.stage(Change::write(gitkeep_path, ""));
+
+        // 2. 在集合目录下创建 `collection.json`，存储唯一 ID
+        tx.stage(Change::write(
+            collection_meta_path(name),
+            collection.render()?,
+        ));
+
+        // 两个文件在同一个 commit 中落地，不会留下半创建的集合
+        tx.commit(&format!("Create collection {}", name)).await?;
+
+        Ok(collection)
+    }
+
+    /// 列出所有集合名称（以存在 `collection.json` 为准）
+    pub async fn list_collections(&self) -> AppResult<Vec<String>> {
+        let paths = self.storage.list_files(COLLECTIONS_DIR).await.into_app()?;
+        let prefix = format!("{}/", COLLECTIONS_DIR);
+
+        let mut names: Vec<String> = paths
+            .iter()
+            .filter_map(|path| path.strip_prefix(&prefix))
+            .filter_map(|rest| rest.strip_suffix(COLLECTION_META_FILE))
+            .filter_map(|dir| dir.strip_suffix('/'))
+            .filter(|name| !name.contains('/'))
+            .map(str::to_string)
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// 读取集合的 `collection.json`
+    pub async fn get_collection(&self, name: &str) -> AppResult<Collection> {
+        self.load_collection(name)
+            .await
+            .map(|(collection, _)| collection)
+    }
+
+    /// A collection's metadata and the blob SHA of `collection.json` it was read at
+    pub(crate) async fn load_collection(&self, name: &str) -> AppResult<(Collection, String)> {
+        validate_name(name)?;
+
+        let raw = match self.storage.read(&collection_meta_path(name)).await {
+            Ok(raw) => raw,
+            Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => {
+                let context = GBError::Collection(CollectionError::NotFound(name.to_string()));
+                return Err(e.change_context(context));
+            }
+            Err(e) => return Err(e).into_app(),
+        };
+
+        let collection = serde_json::from_str(&raw).map_err(|e| {
+            collection_error(CollectionError::InvalidMetadata(name.to_string()))
+                .attach_printable(e.to_string())
+        })?;
+
+        Ok((collection, git_blob_sha(raw.as_bytes())))
+    }
+
+    /// 重命名集合：移动其下所有文件并更新 `collection.json`，在一个 commit 中完成
+    pub async fn rename_collection(&self, from: &str, to: &str) -> AppResult<Collection> {
+        validate_name(to)?;
+        let current = self.get_collection(from).await?;
+        if self.collection_exists(to).await? {
+            return Err(collection_error(CollectionError::AlreadyExists(
+                to.to_string(),
+            )));
+        }
+
+        // 集合 ID、创建时间和结构版本保持不变，只有名称随之改变
+        let renamed = Collection {
+            name: to.to_string(),
+            ..current
+        };
+
+        let old_dir = collection_dir(from);
+        let new_dir = collection_dir(to);
+        let meta_path = collection_meta_path(from);
+        let indexes_dir = format!("{}/", index_dir(from));
+        let mut tx = self.transaction();
+
+        // 删除原文件时以读取时的 SHA 为前置条件，期间被他人修改则整个 commit 以冲突失败
+        for path in self.storage.list_files(&old_dir).await.into_app()? {
+            let new_path = format!("{}{}", new_dir, &path[old_dir.len()..]);
+            let raw = self.storage.read(&path).await.into_app()?;
+            let sha = git_blob_sha(raw.as_bytes());
+            let content = if path == meta_path {
+                renamed.render()?
+            } else if path.starts_with(&indexes_dir) {
+                // Index IDs are derived from the collection name as well
+                let mut index = Index::parse(&raw)?;
+                index.index_id = coder::generate_index_id(&index.name, to).into_app()?;
+                index.render()?
+            } else {
+                raw
+            };
+
+            tx.stage(Change::write(new_path, content));
+            tx.stage(Change::delete(path).expecting(sha));
+        }
+
+        // 结构定义随集合移动，重命名后的文档在同一个 commit 中按它校验
+        if let Some(schema) = self.read_optional(&schema_path(from)).await? {
+            let sha = git_blob_sha(schema.as_bytes());
+            tx.stage(Change::write(schema_path(to), schema));
+            tx.stage(Change::delete(schema_path(from)).expecting(sha));
+        }
+
+        tx.commit(&format!("Rename collection {} to {}", from, to))
+            .await?;
+
+        Ok(renamed)
+    }
+
+    /// 删除集合及其下所有文档和索引，在一个 commit 中完成
+    pub async fn drop_collection(&self, name: &str) -> AppResult<()> {
+        self.get_collection(name).await?;
+
+        let mut tx = self.transaction();
+        // 以列出时的 SHA 为前置条件，期间被他人修改的文件让整个 commit 以冲突失败
+        for path in self
+            .storage
+            .list_files(&collection_dir(name))
+            .await
+            .into_app()?
+        {
+            if let Some(sha) = self.storage.sha(&path).await.into_app()? {
+                tx.stage(Change::delete(path).expecting(sha));
+            }
+        }
+        // 同名集合重新创建时不应继承旧的结构定义
+        let schema = schema_path(name);
+        if let Some(sha) = self.storage.sha(&schema).await.into_app()? {
+            tx.stage(Change::delete(schema).expecting(sha));
+        }
+
+        tx.commit(&format!("Drop collection {}", name)).await?;
+
+        Ok(())
+    }
+
+    /// The content of `path`, or `None` if there is no such file
+    async fn read_optional(&self, path: &str) -> AppResult<Option<String>> {
+        match self.storage.read(path).await {
+            Ok(raw) => Ok(Some(raw)),
+            Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e).into_app(),
+        }
+    }
+
+    async fn collection_exists(&self, name: &str) -> AppResult<bool> {
+        match self.get_collection(name).await {
+            Ok(_) => Ok(true),
+            Err(e)
+                if matches!(
+                    e.current_context(),
+                    GBError::Collection(CollectionError::NotFound(_))
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::document_path;
+    use crate::error::StorageResult;
+    use crate::index::IndexSpec;
+    use crate::storage::{FileMeta, InMemoryStorage, ListOptions, ListPage, StorageBackend};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory storage where another writer sneaks in one write right before the next commit
+    struct RacingStorage {
+        files: InMemoryStorage,
+        race: Arc<Mutex<Option<(String, String)>>>,
+    }
+
+    #[async_trait]
+    impl StorageBackend for RacingStorage {
+        async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+            self.files.write(path, content).await
+        }
+
+        async fn write_if(
+            &self,
+            path: &str,
+            content: &str,
+            expected_sha: Option<&str>,
+        ) -> StorageResult<FileMeta> {
+            self.files.write_if(path, content, expected_sha).await
+        }
+
+        async fn read(&self, path: &str) -> StorageResult<String> {
+            self.files.read(path).await
+        }
+
+        async fn delete(&self, path: &str) -> StorageResult<()> {
+            self.files.delete(path).await
+        }
+
+        async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+            self.files.stat(path).await
+        }
+
+        async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+            self.files.list(prefix, options).await
+        }
+
+        async fn commit(&self, message: &str, changes: &[Change]) -> StorageResult<Option<String>> {
+            let race = self.race.lock().unwrap().take();
+            if let Some((path, content)) = race {
+                self.files.write(&path, &content).await?;
+            }
+            self.files.commit(message, changes).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collection_lifecycle() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+
+        let notes = gitbase.create_collection("notes").await.unwrap();
+        gitbase.create_collection("tasks").await.unwrap();
+        let err = gitbase.create_collection("notes").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Collection(CollectionError::AlreadyExists(_))
+        ));
+        assert_eq!(
+            gitbase.list_collections().await.unwrap(),
+            vec!["notes", "tasks"]
+        );
+        assert_eq!(gitbase.get_collection("notes").await.unwrap(), notes);
+
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Hello"}))
+            .await
+            .unwrap();
+
+        let by_title = gitbase
+            .create_index("notes", IndexSpec::new("by_title", "title"))
+            .await
+            .unwrap();
+
+        let journal = gitbase.rename_collection("notes", "journal").await.unwrap();
+        let moved = gitbase.get_index("journal", "by_title").await.unwrap();
+        assert_ne!(moved.index_id, by_title.index_id);
+        assert_eq!(moved.entries, by_title.entries);
+        assert_eq!(journal.created_at, notes.created_at);
+        assert_eq!(journal.collection_id, notes.collection_id);
+        assert_eq!(
+            gitbase.get_collection("journal").await.unwrap(),
+            Collection {
+                name: "journal".to_string(),
+                ..notes.clone()
+            }
+        );
+        assert_eq!(
+            gitbase.list_collections().await.unwrap(),
+            vec!["journal", "tasks"]
+        );
+        assert_eq!(
+            gitbase
+                .get_document("journal", &doc.id)
+                .await
+                .unwrap()
+                .content,
+            json!({"title": "Hello"})
+        );
+        assert!(storage
+            .list_files("collections/notes")
+            .await
+            .unwrap()
+            .is_empty());
+
+        gitbase.drop_collection("journal").await.unwrap();
+        assert_eq!(gitbase.list_collections().await.unwrap(), vec!["tasks"]);
+        let err = gitbase.get_collection("journal").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Collection(CollectionError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_names() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+
+        for name in ["", "..", "a/b", "_indexes"] {
+            let err = gitbase.create_collection(name).await.unwrap_err();
+            assert!(matches!(
+                err.current_context(),
+                GBError::Collection(CollectionError::InvalidName(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rename_keeps_concurrent_updates() {
+        let files = InMemoryStorage::new();
+        let race = Arc::new(Mutex::new(None));
+        let storage = RacingStorage {
+            files: files.clone(),
+            race: race.clone(),
+        };
+        let gitbase = GitBase::with_storage(storage);
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Rust"}))
+            .await
+            .unwrap();
+
+        // A document updated after the rename read it fails the rename instead of being lost
+        let path = document_path("notes", &doc.id);
+        let raw = files.read(&path).await.unwrap();
+        let updated = raw.replace("Rust", "Go");
+        *race.lock().unwrap() = Some((path.clone(), updated.clone()));
+
+        let err = gitbase
+            .rename_collection("notes", "posts")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Storage(StorageError::Conflict { .. })
+        ));
+        assert_eq!(files.read(&path).await.unwrap(), updated);
+        assert!(!gitbase.collection_exists("posts").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_drop_keeps_concurrent_updates() {
+        let files = InMemoryStorage::new();
+        let race = Arc::new(Mutex::new(None));
+        let storage = RacingStorage {
+            files: files.clone(),
+            race: race.clone(),
+        };
+        let gitbase = GitBase::with_storage(storage);
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Rust"}))
+            .await
+            .unwrap();
+
+        // A document updated after the drop listed it fails the drop instead of being lost
+        let path = document_path("notes", &doc.id);
+        let raw = files.read(&path).await.unwrap();
+        let updated = raw.replace("Rust", "Go");
+        *race.lock().unwrap() = Some((path.clone(), updated.clone()));
+
+        let err = gitbase.drop_collection("notes").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Storage(StorageError::Conflict { .. })
+        ));
+        assert_eq!(files.read(&path).await.unwrap(), updated);
+        assert!(gitbase.collection_exists("notes").await.unwrap());
+    }
+}
@@ -0,0 +1,206 @@
+use error_stack::Report;
+use serde::{Deserialize, Serialize};
+
+use crate::database::FORMAT_VERSION;
+use crate::document::GITBASE_DIR;
+use crate::error::{AppResult, DatabaseError, GBError, IntoAppResult, StorageError};
+use crate::storage::{Change, StorageBackend};
+use crate::GitBase;
+
+pub const CONFIG_FILE: &str = "config.json";
+
+/// `.gitbase/config.json`
+pub fn config_path() -> String {
+    format!("{}/{}", GITBASE_DIR, CONFIG_FILE)
+}
+
+/// Database-wide settings stored in `.gitbase/config.json`. Missing keys take their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Layout version of the database, see [`FORMAT_VERSION`]
+    pub format_version: u32,
+    /// Branch documents are read from and committed to, the repository's default branch when
+    /// `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    pub id_scheme: IdScheme,
+    pub document_format: DocumentFormat,
+    /// Number of files kept in the read cache
+    pub cache_size: usize,
+    /// Author and committer of every commit, the credentials' own identity when `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<Author>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            format_version: FORMAT_VERSION,
+            default_branch: None,
+            id_scheme: IdScheme::default(),
+            document_format: DocumentFormat::default(),
+            cache_size: 100,
+            author: None,
+        }
+    }
+}
+
+/// How new document IDs are generated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdScheme {
+    /// `gbdoc1…`, a Bech32 hash of the content and creation time
+    #[default]
+    Bech32,
+    /// The UTC creation time down to nanoseconds, so IDs sort by age
+    Timestamp,
+}
+
+/// How document files are rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    /// Indented JSON, readable and diffable in the GitHub UI
+    #[default]
+    Pretty,
+    /// JSON on a single line
+    Compact,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Author {
+    pub name: String,
+    pub email: String,
+}
+
+impl Config {
+    pub(crate) fn parse(raw: &str) -> AppResult<Self> {
+        Ok(serde_json::from_str(raw).map_err(GBError::from)?)
+    }
+
+    pub(crate) fn render(&self) -> AppResult<String> {
+        let mut raw = serde_json::to_string_pretty(self).map_err(GBError::from)?;
+        raw.push('\n');
+        Ok(raw)
+    }
+
+    /// The configuration stored in `storage`, or the defaults if there is none. Fails with
+    /// [`DatabaseError::UnsupportedFormat`] for databases written by a newer version.
+    pub(crate) async fn load(storage: &dyn StorageBackend) -> AppResult<Self> {
+        match storage.read(&config_path()).await {
+            Ok(raw) => {
+                let config = Self::parse(&raw)?;
+                if config.format_version > FORMAT_VERSION {
+                    return Err(Report::new(GBError::Database(
+                        DatabaseError::UnsupportedFormat {
+                            found: config.format_version,
+                            supported: FORMAT_VERSION,
+                        },
+                    )));
+                }
+                Ok(config)
+            }
+            Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => {
+                Ok(Config::default())
+            }
+            Err(e) => Err(e).into_app(),
+        }
+    }
+}
+
+impl GitBase {
+    /// 当前实例使用的配置
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// 写入 `.gitbase/config.json`，下次打开数据库时生效
+    pub async fn save_config(&self, config: &Config) -> AppResult<()> {
+        let mut tx = self.transaction();
+        tx.stage(Change::write(config_path(), config.render()?));
+        tx.commit("Update configuration").await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::fake_github::{self, FakeGitHub};
+    use crate::storage::InMemoryStorage;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_config() {
+        let storage = InMemoryStorage::new();
+        assert_eq!(Config::load(&storage).await.unwrap(), Config::default());
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+
+        let raw = r#"{"id_scheme": "timestamp", "document_format": "compact"}"#;
+        let config = Config::parse(raw).unwrap();
+        assert_eq!(config.default_branch, None);
+        assert_eq!(config.cache_size, 100);
+
+        gitbase.save_config(&config).await.unwrap();
+        let gitbase = GitBase::open(storage.clone()).await.unwrap();
+        assert_eq!(gitbase.config(), &config);
+
+        gitbase.create_collection("notes").await.unwrap();
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Rust"}))
+            .await
+            .unwrap();
+        assert!(doc.id.chars().all(|c| c.is_ascii_digit()));
+        let raw = storage
+            .read(&crate::document::document_path("notes", &doc.id))
+            .await
+            .unwrap();
+        assert_eq!(raw.lines().count(), 1);
+
+        let newer = Config {
+            format_version: FORMAT_VERSION + 1,
+            ..config
+        };
+        gitbase.save_config(&newer).await.unwrap();
+        let err = GitBase::open(storage).await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Database(DatabaseError::UnsupportedFormat { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_open_github_branch() {
+        // Without a configured branch the database stays on the repository's default branch
+        let github = FakeGitHub::start(|request| match request.path.as_str() {
+            "/repos/octo/notes" => fake_github::repository("octo", "notes", "master"),
+            _ => fake_github::not_found(),
+        })
+        .await;
+        let gitbase = GitBase::open_github(github.storage("octo", "notes"))
+            .await
+            .unwrap();
+        assert_eq!(gitbase.branch, "master");
+        assert_eq!(gitbase.config(), &Config::default());
+        assert!(github
+            .requests()
+            .iter()
+            .all(|request| !request.path.contains("main")));
+
+        let github = FakeGitHub::start(|request| match request.path.as_str() {
+            "/repos/octo/notes" => fake_github::repository("octo", "notes", "master"),
+            "/repos/octo/notes/contents/.gitbase/config.json?ref=master" => {
+                fake_github::file(".gitbase/config.json", r#"{"default_branch": "data"}"#)
+            }
+            _ => fake_github::not_found(),
+        })
+        .await;
+        let gitbase = GitBase::open_github(github.storage("octo", "notes"))
+            .await
+            .unwrap();
+        assert_eq!(gitbase.branch, "data");
+    }
+}
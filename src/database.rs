@@ -0,0 +1,200 @@
+use error_stack::Report;
+use std::sync::Arc;
+
+use crate::config::{config_path, Config};
+use crate::document::{ATTACHMENTS_DIR, COLLECTIONS_DIR, GITBASE_DIR, GITKEEP_FILE};
+use crate::error::{AppResult, DatabaseError, GBError, IntoAppResult};
+use crate::schema::SCHEMAS_DIR;
+use crate::storage::{Change, GitHubStorage, ListOptions};
+use crate::GitBase;
+
+/// Layout version stamped into `.gitbase/config.json` by [`GitBase::create_database`]
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Files a freshly created repository may already hold and a database can be created next to
+fn is_adoptable(path: &str) -> bool {
+    let name = path.to_ascii_uppercase();
+    ["README", "LICENSE"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+        || path == ".gitignore"
+        || path == ".gitattributes"
+}
+
+fn database_error(err: DatabaseError) -> Report<GBError> {
+    Report::new(GBError::Database(err))
+}
+
+impl GitBase {
+    /// 在 GitHub 上创建数据库：仓库不存在时创建私有仓库，然后在 `config.default_branch`
+    /// （未指定时为仓库的默认分支）上初始化目录结构，并按 `config` 打开数据库
+    pub async fn create(
+        token: &str,
+        owner: &str,
+        repo: &str,
+        config: Config,
+    ) -> AppResult<Arc<Self>> {
+        let storage = GitHubStorage::new(token, owner, repo, None).into_app()?;
+
+        Self::create_github(storage, config).await
+    }
+
+    /// 与 [`GitBase::create`] 相同，但使用已配置好的存储（如自定义客户端）
+    pub async fn create_github(storage: GitHubStorage, config: Config) -> AppResult<Arc<Self>> {
+        if !storage.repository_exists().await.into_app()? {
+            storage.create_repository().await.into_app()?;
+        }
+
+        // 新仓库的默认分支由 GitHub 的账户设置决定，配置未指定分支时沿用它
+        let branch = match &config.default_branch {
+            Some(branch) => branch.clone(),
+            None => storage.default_branch().await.into_app()?,
+        };
+        let mut storage = storage.with_branch(&branch);
+        if let Some(author) = &config.author {
+            storage = storage.with_signature(&author.name, &author.email);
+        }
+
+        let gitbase = Self::on_branch(crate::cached(storage, &config).into_dyn(), config, branch);
+        gitbase.create_database().await?;
+
+        Ok(gitbase)
+    }
+
+    /// 在空仓库中初始化数据库：创建 `.gitbase/`、`.gitbase/schemas/`、`collections/`、
+    /// `attachments/`，并写入带格式版本的 `config.json`，在一个 commit 中完成。
+    /// 仓库中只允许已有 README、LICENSE、`.gitignore` 等文件
+    pub async fn create_database(&self) -> AppResult<()> {
+        let root = self
+            .storage
+            .list("", &ListOptions::default())
+            .await
+            .into_app()?;
+        if self.storage.exists(&config_path()).await.into_app()? {
+            return Err(database_error(DatabaseError::AlreadyInitialized));
+        }
+        if let Some(entry) = root.entries.iter().find(|entry| !is_adoptable(&entry.path)) {
+            return Err(database_error(DatabaseError::NotEmpty(entry.path.clone())));
+        }
+
+        let config = Config {
+            format_version: FORMAT_VERSION,
+            ..self.config.clone()
+        };
+
+        let mut tx = self.transaction();
+        tx.stage(Change::write(config_path(), config.render()?));
+        for dir in [
+            format!("{}/{}", GITBASE_DIR, SCHEMAS_DIR),
+            COLLECTIONS_DIR.to_string(),
+            ATTACHMENTS_DIR.to_string(),
+        ] {
+            // Git does not track empty directories
+            tx.stage(Change::write(format!("{}/{}", dir, GITKEEP_FILE), ""));
+        }
+        tx.commit("Initialize GitBase database").await?;
+
+        Ok(())
+    }
+
+    /// Fail with [`DatabaseError::NotInitialized`] unless the storage holds a database
+    pub(crate) async fn ensure_database(&self) -> AppResult<()> {
+        if !self.storage.exists(&config_path()).await.into_app()? {
+            return Err(database_error(DatabaseError::NotInitialized));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::fake_github::{self, FakeGitHub};
+    use crate::storage::{InMemoryStorage, StorageBackend};
+
+    #[tokio::test]
+    async fn test_create_database() {
+        let storage = InMemoryStorage::new();
+        storage.write("README.md", "# notes\n").await.unwrap();
+        let gitbase = GitBase::with_storage(storage.clone());
+
+        let err = gitbase.create_collection("notes").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Database(DatabaseError::NotInitialized)
+        ));
+
+        gitbase.create_database().await.unwrap();
+        let mut files = storage.list_files("").await.unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                ".gitbase/config.json",
+                ".gitbase/schemas/.gitkeep",
+                "README.md",
+                "attachments/.gitkeep",
+                "collections/.gitkeep",
+            ]
+        );
+        let config = Config::load(&storage).await.unwrap();
+        assert_eq!(config.format_version, FORMAT_VERSION);
+
+        gitbase.create_collection("notes").await.unwrap();
+        let err = gitbase.create_database().await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Database(DatabaseError::AlreadyInitialized)
+        ));
+
+        let storage = InMemoryStorage::new();
+        storage
+            .write("src/main.rs", "fn main() {}\n")
+            .await
+            .unwrap();
+        let err = GitBase::with_storage(storage)
+            .create_database()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Database(DatabaseError::NotEmpty(path)) if path == "src"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_on_default_branch() {
+        let github = FakeGitHub::start(|request| {
+            let path = request.path.strip_prefix("/repos/octo/notes").unwrap_or("");
+            match (request.method.as_str(), path) {
+                ("GET", "") => fake_github::repository("octo", "notes", "master"),
+                ("GET", "/contents/?ref=master") => {
+                    let (_, readme) = fake_github::file("README.md", "# notes\n");
+                    (200, format!("[{}]", readme))
+                }
+                ("GET", "/git/ref/heads/master") => (200, r#"{"object": {"sha": "c0"}}"#.into()),
+                ("GET", "/git/commits/c0") => (200, r#"{"tree": {"sha": "t0"}}"#.into()),
+                ("POST", "/git/blobs" | "/git/trees" | "/git/commits") => {
+                    (201, r#"{"sha": "c1"}"#.into())
+                }
+                ("PATCH", "/git/refs/heads/master") => (200, r#"{"object": {"sha": "c1"}}"#.into()),
+                _ => fake_github::not_found(),
+            }
+        })
+        .await;
+
+        let gitbase = GitBase::create_github(github.storage("octo", "notes"), Config::default())
+            .await
+            .unwrap();
+        assert_eq!(gitbase.branch, "master");
+
+        let requests = github.requests();
+        assert!(requests
+            .iter()
+            .any(|request| request.method == "PATCH" && request.path.ends_with("/heads/master")));
+        assert!(requests
+            .iter()
+            .all(|request| !request.path.contains("main") && !request.body.contains("main")));
+    }
+}
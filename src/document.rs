@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use crate::config::{DocumentFormat, IdScheme};
+use crate::error::{AppResult, GBError, IntoAppResult};
+use crate::{coder, Document, Metadata};
+
+/// System metadata: schemas and configuration
+pub const GITBASE_DIR: &str = ".gitbase";
+pub const COLLECTIONS_DIR: &str = "collections";
+/// Large files such as images, kept outside of the documents
+pub const ATTACHMENTS_DIR: &str = "attachments";
+pub const COLLECTION_META_FILE: &str = "collection.json";
+pub const GITKEEP_FILE: &str = ".gitkeep";
+pub const DOCUMENT_EXT: &str = ".json";
+pub const INDEXES_DIR: &str = "_indexes";
+
+/// On-disk form of a document. The blob SHA of this file is the document's `updated_sha`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DocumentFile {
+    pub id: String,
+    pub created_at: String,
+    pub content: Value,
+}
+
+impl DocumentFile {
+    pub fn new(id: &str, content: Value) -> Self {
+        DocumentFile {
+            id: id.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            content,
+        }
+    }
+
+    pub fn parse(raw: &str) -> AppResult<Self> {
+        Ok(serde_json::from_str(raw).map_err(GBError::from)?)
+    }
+
+    /// Pretty-printed by default so documents stay readable in the GitHub UI
+    pub fn render(&self, format: DocumentFormat) -> AppResult<String> {
+        let mut raw = match format {
+            DocumentFormat::Pretty => serde_json::to_string_pretty(self),
+            DocumentFormat::Compact => serde_json::to_string(self),
+        }
+        .map_err(GBError::from)?;
+        raw.push('\n');
+        Ok(raw)
+    }
+
+    pub fn into_document(self, sha: String) -> Document {
+        Document {
+            id: self.id,
+            content: self.content,
+            meta: Metadata {
+                created_at: self.created_at,
+                updated_sha: sha,
+            },
+        }
+    }
+}
+
+/// Nanosecond timestamp of the last document ID generated by this process
+static LAST_ID_NANOS: AtomicI64 = AtomicI64::new(0);
+
+/// The current time in nanoseconds, moved past the last one handed out so that IDs generated
+/// within the clock's resolution, e.g. in one transaction, never collide
+fn unique_nanos() -> i64 {
+    let now = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    let previous = LAST_ID_NANOS
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    now.max(previous + 1)
+}
+
+/// Generate a fresh document ID from its content and the current time
+pub fn new_document_id(content: &Value, scheme: IdScheme) -> AppResult<String> {
+    let nanos = unique_nanos();
+    match scheme {
+        IdScheme::Bech32 => {
+            coder::generate_document_id(&content.to_string(), nanos as u64).into_app()
+        }
+        IdScheme::Timestamp => Ok(chrono::DateTime::from_timestamp_nanos(nanos)
+            .format("%Y%m%d%H%M%S%9f")
+            .to_string()),
+    }
+}
+
+pub fn collection_dir(collection: &str) -> String {
+    format!("{}/{}", COLLECTIONS_DIR, collection)
+}
+
+pub fn document_path(collection: &str, doc_id: &str) -> String {
+    format!("{}/{}{}", collection_dir(collection), doc_id, DOCUMENT_EXT)
+}
+
+/// The collection and document ID of a document path, `None` for any other file
+pub fn parse_document_path(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix(COLLECTIONS_DIR)?.strip_prefix('/')?;
+    let (collection, file) = rest.split_once('/')?;
+    if file.contains('/') || file == COLLECTION_META_FILE {
+        return None;
+    }
+
+    let doc_id = file.strip_suffix(DOCUMENT_EXT)?;
+    Some((collection, doc_id))
+}
+
+/// Look up a dotted field path such as `author.name` in a document's content
+pub fn field_value<'v>(content: &'v Value, field: &str) -> Option<&'v Value> {
+    field
+        .split('.')
+        .try_fold(content, |value, key| match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// Apply a JSON Merge Patch (RFC 7396): objects merge recursively and `null` removes a key
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let mut doc =
+            json!({"title": "Hello", "tags": ["a"], "meta": {"pinned": true, "color": "red"}});
+        merge_patch(
+            &mut doc,
+            &json!({"title": "Hi", "tags": ["b"], "meta": {"color": null}, "new": 1}),
+        );
+        assert_eq!(
+            doc,
+            json!({"title": "Hi", "tags": ["b"], "meta": {"pinned": true}, "new": 1})
+        );
+    }
+
+    #[test]
+    fn test_unique_document_ids() {
+        let content = json!({"title": "Same"});
+        for scheme in [IdScheme::Timestamp, IdScheme::Bech32] {
+            let ids: Vec<String> = (0..100)
+                .map(|_| new_document_id(&content, scheme).unwrap())
+                .collect();
+            let mut unique = ids.clone();
+            unique.sort();
+            unique.dedup();
+            assert_eq!(unique.len(), ids.len());
+            if scheme == IdScheme::Timestamp {
+                assert_eq!(unique, ids);
+            }
+        }
+    }
+
+    #[test]
+    fn test_document_paths() {
+        assert_eq!(
+            document_path("notes", "gbdoc1abc"),
+            "collections/notes/gbdoc1abc.json"
+        );
+        assert_eq!(
+            parse_document_path("collections/notes/gbdoc1abc.json"),
+            Some(("notes", "gbdoc1abc"))
+        );
+        assert_eq!(
+            parse_document_path("collections/notes/collection.json"),
+            None
+        );
+        assert_eq!(parse_document_path("collections/notes/.gitkeep"), None);
+        assert_eq!(
+            parse_document_path("collections/notes/_indexes/by_tag.json"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_field_value() {
+        let doc = json!({"author": {"name": "Ada"}, "tags": ["a", "b"]});
+        assert_eq!(field_value(&doc, "author.name"), Some(&json!("Ada")));
+        assert_eq!(field_value(&doc, "tags.1"), Some(&json!("b")));
+        assert_eq!(field_value(&doc, "author.email"), None);
+    }
+}
@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum CacheError {
     #[error("Cache synchronization error")]
     SyncError,
@@ -13,4 +13,10 @@ pub enum CacheError {
 
     #[error("Invalid document format")]
     InvalidFormat,
+
+    #[error("Invalid cache configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Cache I/O error: {0}")]
+    Io(String),
 }
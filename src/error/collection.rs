@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum CollectionError {
+    #[error("Collection not found: {0}")]
+    NotFound(String),
+
+    #[error("Collection already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid collection name: {0}")]
+    InvalidName(String),
+
+    #[error("Invalid collection metadata: {0}")]
+    InvalidMetadata(String),
+}
@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum DatabaseError {
+    #[error("Not a GitBase database: .gitbase/config.json is missing")]
+    NotInitialized,
+
+    #[error("Database already initialized")]
+    AlreadyInitialized,
+
+    #[error("Repository is not empty: {0}")]
+    NotEmpty(String),
+
+    #[error(
+        "Unsupported database format version {found}, this version supports up to {supported}"
+    )]
+    UnsupportedFormat { found: u32, supported: u32 },
+}
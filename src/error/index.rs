@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum IndexError {
+    #[error("Index not found: {0}")]
+    NotFound(String),
+
+    #[error("Index already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid index name: {0}")]
+    InvalidName(String),
+
+    #[error("Invalid index: {0}")]
+    InvalidIndex(String),
+
+    #[error("Duplicate key {key} in unique index {index}, already used by document {doc_id}")]
+    UniqueViolation {
+        index: String,
+        key: String,
+        doc_id: String,
+    },
+}
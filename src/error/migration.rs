@@ -0,0 +1,14 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum MigrationError {
+    #[error("Invalid migration: {0}")]
+    InvalidMigration(String),
+
+    #[error("Cannot convert field {field} of document {doc_id}: {reason}")]
+    Conversion {
+        doc_id: String,
+        field: String,
+        reason: String,
+    },
+}
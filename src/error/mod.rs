@@ -1,11 +1,23 @@
 mod cache;
 mod coder;
+mod collection;
+mod database;
+mod index;
+mod migration;
+mod query;
+mod schema;
 mod storage;
 
 use thiserror::Error;
 
 pub use cache::CacheError;
 pub use coder::CoderError;
+pub use collection::CollectionError;
+pub use database::DatabaseError;
+pub use index::IndexError;
+pub use migration::MigrationError;
+pub use query::QueryError;
+pub use schema::{SchemaError, Violation};
 pub use storage::{GitHubStorageError, StorageError};
 
 #[derive(Error, Debug)]
@@ -16,6 +28,27 @@ pub enum GBError {
     #[error("Coder error: {0}")]
     Coder(#[from] CoderError),
 
+    #[error("Collection error: {0}")]
+    Collection(#[from] CollectionError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+
+    #[error("Index error: {0}")]
+    Index(#[from] IndexError),
+
+    #[error("Query error: {0}")]
+    Query(#[from] QueryError),
+
+    #[error("Migration error: {0}")]
+    Migration(#[from] MigrationError),
+
+    #[error("Schema error: {0}")]
+    Schema(#[from] SchemaError),
+
+    #[error("Cache error: {0}")]
+    Cache(#[from] CacheError),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -26,12 +59,53 @@ pub enum GBError {
     Other(String),
 }
 
-pub type CacheResult<T> = Result<T, CacheError>;
+pub type CacheResult<T> = error_stack::Result<T, CacheError>;
 pub type AppResult<T> = error_stack::Result<T, GBError>;
 pub type CoderResult<T> = error_stack::Result<T, CoderError>;
 pub type StorageResult<T> = error_stack::Result<T, StorageError>;
 pub type GitHubStorageResult<T> = error_stack::Result<T, GitHubStorageError>;
 
+/// Lift a subsystem result into an [`AppResult`], keeping the original report frames
+pub(crate) trait IntoAppResult<T> {
+    fn into_app(self) -> AppResult<T>;
+}
+
+impl<T> IntoAppResult<T> for StorageResult<T> {
+    fn into_app(self) -> AppResult<T> {
+        self.map_err(|report| {
+            let context = GBError::Storage(report.current_context().clone());
+            report.change_context(context)
+        })
+    }
+}
+
+impl<T> IntoAppResult<T> for GitHubStorageResult<T> {
+    fn into_app(self) -> AppResult<T> {
+        self.map_err(|report| {
+            let context = GBError::Storage(StorageError::GitHub(report.current_context().clone()));
+            report.change_context(context)
+        })
+    }
+}
+
+impl<T> IntoAppResult<T> for CacheResult<T> {
+    fn into_app(self) -> AppResult<T> {
+        self.map_err(|report| {
+            let context = GBError::Cache(report.current_context().clone());
+            report.change_context(context)
+        })
+    }
+}
+
+impl<T> IntoAppResult<T> for CoderResult<T> {
+    fn into_app(self) -> AppResult<T> {
+        self.map_err(|report| {
+            let context = GBError::Other(report.current_context().to_string());
+            report.change_context(context)
+        })
+    }
+}
+
 // pub trait ErrorExt<T, E> {
 //     fn with_context<C, F>(self, context_provider: F) -> Result<T, C>
 //     where
@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum QueryError {
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(String),
+
+    #[error("Invalid cursor: {0}")]
+    InvalidCursor(String),
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum SchemaError {
+    #[error("Invalid schema for collection {collection}: {reason}")]
+    InvalidSchema { collection: String, reason: String },
+
+    #[error(
+        "Document {doc_id} does not match the schema of {collection}: {}",
+        Violations(violations)
+    )]
+    Validation {
+        collection: String,
+        doc_id: String,
+        violations: Vec<Violation>,
+    },
+}
+
+/// One failed schema constraint of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// JSON Pointer to the offending value in the document content, empty for the root
+    pub path: String,
+    /// JSON Pointer to the failing keyword in the schema
+    pub schema_path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+struct Violations<'a>(&'a [Violation]);
+
+impl fmt::Display for Violations<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, violation) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
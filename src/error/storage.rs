@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum StorageError {
     #[error("GitHub storage error: {0}")]
     GitHub(#[from] GitHubStorageError),
@@ -19,9 +19,19 @@ pub enum StorageError {
 
     #[error("I/O error: {0}")]
     IoError(String),
+
+    #[error("Git error: {0}")]
+    Git(String),
+
+    #[error("Conflict on {path}: expected {expected:?}, found {current:?}")]
+    Conflict {
+        path: String,
+        expected: Option<String>,
+        current: Option<String>,
+    },
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum GitHubStorageError {
     #[error("GitHub API error: {0}")]
     ApiError(String),
@@ -0,0 +1,1016 @@
+use error_stack::Report;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::document::{
+    collection_dir, document_path, field_value, parse_document_path, DocumentFile, DOCUMENT_EXT,
+    INDEXES_DIR,
+};
+use crate::error::{AppResult, GBError, IndexError, IntoAppResult, StorageError};
+use crate::search::tokenize;
+use crate::storage::{git_blob_sha, Change, ListOptions};
+use crate::{coder, GitBase};
+
+/// Contents of `collections/<name>/_indexes/<index>.json`: the keys of the indexed documents,
+/// in ascending order, each with the IDs of the documents holding it.
+///
+/// On a single field the key is the field's value. A document whose field is an array is
+/// indexed under each of its elements, so an index on `tags` finds documents by any one tag,
+/// and documents without the field are not indexed. On several fields the key is the array of
+/// their values, with `null` for missing ones and arrays expanded the same way; documents
+/// missing all of them are not indexed.
+///
+/// A [`IndexKind::Text`] index is inverted instead: its keys are the words of the string
+/// fields, see [`GitBase::search`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub name: String,
+    pub index_id: String,
+    #[serde(default)]
+    pub kind: IndexKind,
+    pub fields: Vec<String>,
+    /// Reject writes that would index two documents under the same key
+    #[serde(default)]
+    pub unique: bool,
+    pub entries: Vec<IndexEntry>,
+    /// Number of words in each indexed document, text indexes only
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub lengths: BTreeMap<String, usize>,
+    /// IDs of the documents whose field holds an array, value indexes on one field only
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub arrays: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexKind {
+    /// Keyed by field values, used by queries
+    #[default]
+    Value,
+    /// Keyed by the words of string fields, used by [`GitBase::search`]
+    Text,
+}
+
+/// What to index, passed to [`GitBase::create_index`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSpec {
+    pub name: String,
+    pub kind: IndexKind,
+    pub fields: Vec<String>,
+    pub unique: bool,
+}
+
+impl IndexSpec {
+    pub fn new(name: &str, field: &str) -> Self {
+        Self::compound(name, &[field])
+    }
+
+    pub fn compound(name: &str, fields: &[&str]) -> Self {
+        IndexSpec {
+            name: name.to_string(),
+            kind: IndexKind::Value,
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            unique: false,
+        }
+    }
+
+    /// A full-text index over string fields, or arrays of strings, such as Markdown bodies
+    pub fn text(name: &str, fields: &[&str]) -> Self {
+        IndexSpec {
+            kind: IndexKind::Text,
+            ..Self::compound(name, fields)
+        }
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub value: Value,
+    pub ids: Vec<String>,
+    /// How often a text index's word occurs in each document, in the order of `ids`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub counts: Vec<usize>,
+}
+
+/// How an index file differs from the documents, as found by [`GitBase::verify_indexes`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexReport {
+    pub index: String,
+    /// `(key, document ID)` pairs the documents call for but the index lacks
+    pub missing: Vec<(Value, String)>,
+    /// Pairs the index holds for existing documents that no longer have that key
+    pub stale: Vec<(Value, String)>,
+    /// IDs in the index of documents that do not exist
+    pub dangling: Vec<String>,
+}
+
+impl IndexReport {
+    fn compare(stored: &Index, rebuilt: &Index, documents: &BTreeSet<String>) -> Self {
+        let stored_postings = stored.postings();
+        let rebuilt_postings = rebuilt.postings();
+
+        let mut dangling: Vec<String> = stored_postings
+            .keys()
+            .map(|(_, id)| id.to_string())
+            .filter(|id| !documents.contains(id))
+            .chain(
+                stored
+                    .lengths
+                    .keys()
+                    .chain(&stored.arrays)
+                    .filter(|id| !documents.contains(*id))
+                    .cloned(),
+            )
+            .collect();
+        dangling.sort();
+        dangling.dedup();
+
+        IndexReport {
+            index: stored.name.clone(),
+            missing: postings_not_in(&rebuilt_postings, &stored_postings, documents),
+            stale: postings_not_in(&stored_postings, &rebuilt_postings, documents),
+            dangling,
+        }
+    }
+
+    /// Whether the index matches the documents
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.stale.is_empty() && self.dangling.is_empty()
+    }
+}
+
+type Postings<'a> = BTreeMap<(String, &'a str), (&'a Value, usize)>;
+
+/// The pairs of `from` for existing documents that `other` lacks or counts differently
+fn postings_not_in(
+    from: &Postings,
+    other: &Postings,
+    documents: &BTreeSet<String>,
+) -> Vec<(Value, String)> {
+    from.iter()
+        .filter(|(pair, posting)| other.get(pair) != Some(posting))
+        .filter(|((_, id), _)| documents.contains(*id))
+        .map(|((_, id), (value, _))| ((*value).clone(), id.to_string()))
+        .collect()
+}
+
+impl Index {
+    fn new(collection: &str, spec: &IndexSpec) -> AppResult<Self> {
+        Ok(Index {
+            name: spec.name.clone(),
+            index_id: coder::generate_index_id(&spec.name, collection).into_app()?,
+            kind: spec.kind,
+            fields: spec.fields.clone(),
+            unique: spec.unique,
+            entries: Vec::new(),
+            lengths: BTreeMap::new(),
+            arrays: BTreeSet::new(),
+        })
+    }
+
+    /// The same index with no documents in it
+    fn rebuilt(&self) -> Self {
+        Index {
+            entries: Vec::new(),
+            lengths: BTreeMap::new(),
+            arrays: BTreeSet::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Every `(key, document ID)` pair of the index with the pair's count
+    fn postings(&self) -> Postings<'_> {
+        let mut postings = BTreeMap::new();
+        for entry in &self.entries {
+            for (i, id) in entry.ids.iter().enumerate() {
+                let count = entry.counts.get(i).copied().unwrap_or(1);
+                postings.insert(
+                    (entry.value.to_string(), id.as_str()),
+                    (&entry.value, count),
+                );
+            }
+        }
+        postings
+    }
+
+    pub(crate) fn parse(raw: &str) -> AppResult<Self> {
+        serde_json::from_str(raw)
+            .map_err(|e| Report::new(GBError::Index(IndexError::InvalidIndex(e.to_string()))))
+    }
+
+    /// Pretty-printed like documents, so index diffs stay readable
+    pub(crate) fn render(&self) -> AppResult<String> {
+        let mut raw = serde_json::to_string_pretty(self).map_err(GBError::from)?;
+        raw.push('\n');
+        Ok(raw)
+    }
+
+    /// The indexed field, unless this is a compound index
+    pub fn field(&self) -> Option<&str> {
+        match self.fields.as_slice() {
+            [field] => Some(field),
+            _ => None,
+        }
+    }
+
+    /// The key a document is indexed under for the given field values, or `None` if the values
+    /// do not form a key of this index
+    pub fn key(&self, values: &[Value]) -> Option<Value> {
+        match values {
+            [value] if self.fields.len() == 1 => Some(value.clone()),
+            values if values.len() == self.fields.len() => Some(Value::Array(values.to_vec())),
+            _ => None,
+        }
+    }
+
+    /// IDs of the documents indexed under `value`, a key as built by [`Index::key`]
+    pub fn lookup(&self, value: &Value) -> &[String] {
+        self.entry(value).map_or(&[], |entry| &entry.ids)
+    }
+
+    pub fn entry(&self, value: &Value) -> Option<&IndexEntry> {
+        self.position(value).ok().map(|i| &self.entries[i])
+    }
+
+    /// Move `doc_id` from the keys of its `old` content to those of its `new` content, where
+    /// `None` means the document does not exist. Returns whether the index changed, or fails
+    /// with [`IndexError::UniqueViolation`] if a unique key is taken by another document.
+    pub(crate) fn update(
+        &mut self,
+        doc_id: &str,
+        old: Option<&Value>,
+        new: Option<&Value>,
+    ) -> AppResult<bool> {
+        let content = new;
+        let old = old.map(|content| self.keys(content)).unwrap_or_default();
+        let new = new.map(|content| self.keys(content)).unwrap_or_default();
+        if old == new {
+            return Ok(self.track_array(doc_id, content));
+        }
+
+        if self.unique {
+            for (key, _) in &new {
+                if let Some(other) = self.lookup(key).iter().find(|id| *id != doc_id) {
+                    return Err(Report::new(GBError::Index(IndexError::UniqueViolation {
+                        index: self.name.clone(),
+                        key: key.to_string(),
+                        doc_id: other.clone(),
+                    })));
+                }
+            }
+        }
+
+        for (key, _) in &old {
+            if !new.iter().any(|(other, _)| other == key) {
+                self.remove(doc_id, key);
+            }
+        }
+        for (key, count) in &new {
+            self.insert(doc_id, key, *count);
+        }
+
+        if self.kind == IndexKind::Text {
+            let length = new.iter().map(|(_, count)| count).sum();
+            if length > 0 {
+                self.lengths.insert(doc_id.to_string(), length);
+            } else {
+                self.lengths.remove(doc_id);
+            }
+        }
+        self.track_array(doc_id, content);
+        Ok(true)
+    }
+
+    /// Record whether the indexed field of `content` holds an array, since its elements sort
+    /// differently from the array itself. Returns whether the record changed.
+    fn track_array(&mut self, doc_id: &str, content: Option<&Value>) -> bool {
+        let is_array = match (self.kind, self.field(), content) {
+            (IndexKind::Value, Some(field), Some(content)) => {
+                matches!(field_value(content, field), Some(Value::Array(_)))
+            }
+            _ => false,
+        };
+        if is_array {
+            self.arrays.insert(doc_id.to_string())
+        } else {
+            self.arrays.remove(doc_id)
+        }
+    }
+
+    /// The keys of `content`, sorted, each with its number of occurrences
+    fn keys(&self, content: &Value) -> Vec<(Value, usize)> {
+        if self.kind == IndexKind::Text {
+            return self.words(content);
+        }
+
+        let mut keys = match self.fields.as_slice() {
+            [field] => match field_value(content, field) {
+                Some(Value::Array(items)) => items.clone(),
+                Some(value) => vec![value.clone()],
+                None => Vec::new(),
+            },
+            fields => {
+                let values: Vec<Option<&Value>> = fields
+                    .iter()
+                    .map(|field| field_value(content, field))
+                    .collect();
+                if values.iter().all(Option::is_none) {
+                    return Vec::new();
+                }
+
+                // One key per combination of array elements, so each field can still be
+                // matched against any single element
+                let mut keys = vec![Vec::with_capacity(fields.len())];
+                for value in values {
+                    let options = match value {
+                        Some(Value::Array(items)) => items.clone(),
+                        Some(value) => vec![value.clone()],
+                        None => vec![Value::Null],
+                    };
+                    keys = keys
+                        .into_iter()
+                        .flat_map(|key: Vec<Value>| {
+                            options.iter().map(move |option| {
+                                let mut key = key.clone();
+                                key.push(option.clone());
+                                key
+                            })
+                        })
+                        .collect();
+                }
+                keys.into_iter().map(Value::Array).collect()
+            }
+        };
+        keys.sort_by(compare_values);
+        keys.dedup();
+        keys.into_iter().map(|key| (key, 1)).collect()
+    }
+
+    fn words(&self, content: &Value) -> Vec<(Value, usize)> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for field in &self.fields {
+            let texts = match field_value(content, field) {
+                Some(Value::String(text)) => vec![text.as_str()],
+                Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            for word in texts.into_iter().flat_map(tokenize) {
+                *counts.entry(word).or_default() += 1;
+            }
+        }
+
+        counts
+            .into_iter()
+            .map(|(word, count)| (Value::String(word), count))
+            .collect()
+    }
+
+    fn position(&self, value: &Value) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|entry| compare_values(&entry.value, value))
+    }
+
+    fn insert(&mut self, doc_id: &str, key: &Value, count: usize) {
+        let text = self.kind == IndexKind::Text;
+        let i = self.position(key).unwrap_or_else(|i| {
+            let entry = IndexEntry {
+                value: key.clone(),
+                ids: Vec::new(),
+                counts: Vec::new(),
+            };
+            self.entries.insert(i, entry);
+            i
+        });
+
+        let entry = &mut self.entries[i];
+        match entry.ids.binary_search_by(|id| id.as_str().cmp(doc_id)) {
+            Ok(at) if text => entry.counts[at] = count,
+            Ok(_) => {}
+            Err(at) => {
+                entry.ids.insert(at, doc_id.to_string());
+                if text {
+                    entry.counts.insert(at, count);
+                }
+            }
+        }
+    }
+
+    /// Remove `doc_id` from every key, for when the keys it was indexed under are unknown.
+    /// Returns whether the index changed.
+    fn purge(&mut self, doc_id: &str) -> bool {
+        let mut changed = false;
+        for entry in &mut self.entries {
+            if let Ok(at) = entry.ids.binary_search_by(|id| id.as_str().cmp(doc_id)) {
+                entry.ids.remove(at);
+                if !entry.counts.is_empty() {
+                    entry.counts.remove(at);
+                }
+                changed = true;
+            }
+        }
+        self.entries.retain(|entry| !entry.ids.is_empty());
+        changed |= self.lengths.remove(doc_id).is_some();
+        changed |= self.arrays.remove(doc_id);
+        changed
+    }
+
+    fn remove(&mut self, doc_id: &str, key: &Value) {
+        if let Ok(i) = self.position(key) {
+            let entry = &mut self.entries[i];
+            if let Ok(at) = entry.ids.binary_search_by(|id| id.as_str().cmp(doc_id)) {
+                entry.ids.remove(at);
+                if !entry.counts.is_empty() {
+                    entry.counts.remove(at);
+                }
+            }
+            if entry.ids.is_empty() {
+                self.entries.remove(i);
+            }
+        }
+    }
+}
+
+/// Total order over JSON values used to sort index entries: `null` < booleans < numbers <
+/// strings < arrays < objects, comparing values of the same type naturally
+pub(crate) fn compare_values(a: &Value, b: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (
+                a.as_f64().unwrap_or_default(),
+                b.as_f64().unwrap_or_default(),
+            );
+            a.total_cmp(&b)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare_values(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(_), Value::Object(_)) => a.to_string().cmp(&b.to_string()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+pub fn index_dir(collection: &str) -> String {
+    format!("{}/{}", collection_dir(collection), INDEXES_DIR)
+}
+
+pub fn index_path(collection: &str, name: &str) -> String {
+    format!("{}/{}{}", index_dir(collection), name, DOCUMENT_EXT)
+}
+
+/// An index name becomes a file name under `_indexes/`
+fn validate_name(name: &str) -> AppResult<()> {
+    let valid = !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']);
+    if !valid {
+        return Err(Report::new(GBError::Index(IndexError::InvalidName(
+            name.to_string(),
+        ))));
+    }
+
+    Ok(())
+}
+
+fn is_not_found(report: &Report<StorageError>) -> bool {
+    matches!(report.current_context(), StorageError::NotFound(_))
+}
+
+impl GitBase {
+    /// 按 `spec` 在集合上创建索引，并为已有文档建立索引项；
+    /// 唯一索引遇到重复值时返回 `IndexError::UniqueViolation`
+    pub async fn create_index(&self, collection: &str, spec: IndexSpec) -> AppResult<Index> {
+        let name = spec.name.as_str();
+        validate_name(name)?;
+        if spec.fields.is_empty() {
+            return Err(Report::new(GBError::Index(IndexError::InvalidIndex(
+                format!("Index {} has no fields", name),
+            ))));
+        }
+        if spec.kind == IndexKind::Text && spec.unique {
+            return Err(Report::new(GBError::Index(IndexError::InvalidIndex(
+                format!("Text index {} cannot be unique", name),
+            ))));
+        }
+        self.get_collection(collection).await?;
+
+        let path = index_path(collection, name);
+        if self.storage.exists(&path).await.into_app()? {
+            return Err(Report::new(GBError::Index(IndexError::AlreadyExists(
+                name.to_string(),
+            ))));
+        }
+
+        let mut indexes = [Index::new(collection, &spec)?];
+        self.scan_documents(collection, &mut indexes).await?;
+        let [index] = indexes;
+
+        let mut tx = self.transaction();
+        tx.stage(Change::write(path, index.render()?));
+        tx.commit(&format!("Create index {} on {}", name, collection))
+            .await?;
+
+        Ok(index)
+    }
+
+    /// 列出集合中的所有索引名称
+    pub async fn list_indexes(&self, collection: &str) -> AppResult<Vec<String>> {
+        let page = self
+            .storage
+            .list(&index_dir(collection), &ListOptions::default())
+            .await
+            .into_app()?;
+
+        let names = page
+            .entries
+            .iter()
+            .filter(|entry| entry.is_file())
+            .filter_map(|entry| entry.path.rsplit('/').next())
+            .filter_map(|file| file.strip_suffix(DOCUMENT_EXT))
+            .map(str::to_string)
+            .collect();
+
+        Ok(names)
+    }
+
+    pub async fn get_index(&self, collection: &str, name: &str) -> AppResult<Index> {
+        self.load_index(collection, name)
+            .await
+            .map(|(index, _)| index)
+    }
+
+    /// 删除索引文件，不影响文档
+    pub async fn drop_index(&self, collection: &str, name: &str) -> AppResult<()> {
+        let (_, sha) = self.load_index(collection, name).await?;
+
+        let mut tx = self.transaction();
+        tx.stage(Change::delete(index_path(collection, name)).expecting(sha));
+        tx.commit(&format!("Drop index {} on {}", name, collection))
+            .await?;
+
+        Ok(())
+    }
+
+    /// 从文档重新计算索引，修复手工编辑仓库造成的偏差；索引未偏离时不产生提交
+    pub async fn rebuild_index(&self, collection: &str, name: &str) -> AppResult<Index> {
+        let (stored, sha) = self.load_index(collection, name).await?;
+        let mut indexes = [stored.rebuilt()];
+        self.scan_documents(collection, &mut indexes).await?;
+        let [index] = indexes;
+        if index == stored {
+            return Ok(index);
+        }
+
+        let mut tx = self.transaction();
+        tx.stage(Change::write(index_path(collection, name), index.render()?).expecting(sha));
+        tx.commit(&format!("Rebuild index {} on {}", name, collection))
+            .await?;
+
+        Ok(index)
+    }
+
+    /// 将集合的每个索引与从文档重新计算的结果比较，报告偏差但不修改仓库；
+    /// 用 [`GitBase::rebuild_index`] 修复
+    pub async fn verify_indexes(&self, collection: &str) -> AppResult<Vec<IndexReport>> {
+        let mut stored = Vec::new();
+        for name in self.list_indexes(collection).await? {
+            stored.push(self.get_index(collection, &name).await?);
+        }
+        let mut rebuilt: Vec<Index> = stored.iter().map(Index::rebuilt).collect();
+        let documents = self.scan_documents(collection, &mut rebuilt).await?;
+
+        let reports = stored
+            .iter()
+            .zip(&rebuilt)
+            .map(|(stored, rebuilt)| IndexReport::compare(stored, rebuilt, &documents))
+            .collect();
+
+        Ok(reports)
+    }
+
+    /// Add every document of the collection to `indexes` and return the document IDs
+    async fn scan_documents(
+        &self,
+        collection: &str,
+        indexes: &mut [Index],
+    ) -> AppResult<BTreeSet<String>> {
+        let doc_ids = self.list_documents(collection).await?;
+        for doc_id in &doc_ids {
+            let document = self.read_document(collection, doc_id).await?;
+            for index in indexes.iter_mut() {
+                index.update(doc_id, None, Some(&document.content))?;
+            }
+        }
+
+        Ok(doc_ids.into_iter().collect())
+    }
+
+    /// An index and the blob SHA it was read at
+    async fn load_index(&self, collection: &str, name: &str) -> AppResult<(Index, String)> {
+        validate_name(name)?;
+
+        let raw = match self.storage.read(&index_path(collection, name)).await {
+            Ok(raw) => raw,
+            Err(e) if is_not_found(&e) => {
+                let context = GBError::Index(IndexError::NotFound(name.to_string()));
+                return Err(e.change_context(context));
+            }
+            Err(e) => return Err(e).into_app(),
+        };
+
+        Ok((Index::parse(&raw)?, git_blob_sha(raw.as_bytes())))
+    }
+
+    /// Index file writes that keep every index in step with the document changes of a batch.
+    ///
+    /// Each write carries the SHA the index was read at, so two transactions updating the same
+    /// index cannot silently drop each other's entries. Index files the batch already changes
+    /// itself, as when a collection is renamed or dropped, are left as staged.
+    pub(crate) async fn index_changes(&self, changes: &[Change]) -> AppResult<Vec<Change>> {
+        // The final content of every document the batch touches, by collection
+        let mut touched: BTreeMap<&str, BTreeMap<&str, Option<&str>>> = BTreeMap::new();
+        for change in changes {
+            if let Some((collection, doc_id)) = parse_document_path(change.path()) {
+                let content = match change {
+                    Change::Write { content, .. } => Some(content.as_str()),
+                    Change::Delete { .. } => None,
+                };
+                touched
+                    .entry(collection)
+                    .or_default()
+                    .insert(doc_id, content);
+            }
+        }
+
+        let mut index_changes = Vec::new();
+        for (collection, documents) in touched {
+            let mut indexes = Vec::new();
+            for name in self.list_indexes(collection).await? {
+                let path = index_path(collection, &name);
+                if !changes.iter().any(|change| change.path() == path) {
+                    indexes.push((self.load_index(collection, &name).await?, false));
+                }
+            }
+            if indexes.is_empty() {
+                continue;
+            }
+
+            for (doc_id, content) in documents {
+                // An old version that does not parse cannot tell which keys it was indexed
+                // under, so the document is taken out of all of them, as a rebuild would
+                let (old, unreadable) =
+                    match self.storage.read(&document_path(collection, doc_id)).await {
+                        Ok(raw) => match DocumentFile::parse(&raw) {
+                            Ok(file) => (Some(file.content), false),
+                            Err(_) => (None, true),
+                        },
+                        Err(e) if is_not_found(&e) => (None, false),
+                        Err(e) => return Err(e).into_app(),
+                    };
+                let new = match content {
+                    Some(raw) => Some(DocumentFile::parse(raw)?.content),
+                    None => None,
+                };
+
+                for ((index, _), changed) in &mut indexes {
+                    if unreadable {
+                        *changed |= index.purge(doc_id);
+                    }
+                    *changed |= index.update(doc_id, old.as_ref(), new.as_ref())?;
+                }
+            }
+
+            for ((index, sha), changed) in indexes {
+                if changed {
+                    let path = index_path(collection, &index.name);
+                    index_changes.push(Change::write(path, index.render()?).expecting(sha));
+                }
+            }
+        }
+
+        Ok(index_changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{InMemoryStorage, StorageBackend};
+    use serde_json::json;
+
+    #[test]
+    fn test_compare_values() {
+        let mut values = vec![
+            json!("b"),
+            json!({"a": 1}),
+            json!(10),
+            json!([1, 2]),
+            json!(null),
+            json!(2.5),
+            json!("a"),
+            json!(true),
+            json!([1]),
+        ];
+        values.sort_by(compare_values);
+        assert_eq!(
+            values,
+            vec![
+                json!(null),
+                json!(true),
+                json!(2.5),
+                json!(10),
+                json!("a"),
+                json!("b"),
+                json!([1]),
+                json!([1, 2]),
+                json!({"a": 1}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_index_maintenance() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let rust = gitbase
+            .insert_document("notes", json!({"title": "Rust", "tags": ["lang", "rust"]}))
+            .await
+            .unwrap();
+
+        let index = gitbase
+            .create_index("notes", IndexSpec::new("by_tag", "tags"))
+            .await
+            .unwrap();
+        assert!(index.index_id.starts_with("gbidx1"));
+        assert_eq!(index.lookup(&json!("rust")), [rust.id.as_str()]);
+
+        let go = gitbase
+            .insert_document("notes", json!({"title": "Go", "tags": ["lang"]}))
+            .await
+            .unwrap();
+        gitbase
+            .update_document("notes", &rust.id, json!({"tags": ["lang", "systems"]}))
+            .await
+            .unwrap();
+
+        let index = gitbase.get_index("notes", "by_tag").await.unwrap();
+        let mut both = vec![rust.id.clone(), go.id.clone()];
+        both.sort();
+        assert_eq!(index.lookup(&json!("lang")), both);
+        assert!(index.lookup(&json!("rust")).is_empty());
+        assert_eq!(index.lookup(&json!("systems")), [rust.id.as_str()]);
+
+        gitbase.delete_document("notes", &rust.id).await.unwrap();
+        let index = gitbase.get_index("notes", "by_tag").await.unwrap();
+        assert_eq!(index.lookup(&json!("lang")), [go.id.as_str()]);
+        assert_eq!(index.entries.len(), 1);
+
+        assert_eq!(gitbase.list_indexes("notes").await.unwrap(), vec!["by_tag"]);
+        gitbase.drop_index("notes", "by_tag").await.unwrap();
+        assert!(gitbase.list_indexes("notes").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_index() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let rust = gitbase
+            .insert_document("notes", json!({"tag": "rust"}))
+            .await
+            .unwrap();
+        let go = gitbase
+            .insert_document("notes", json!({"tag": "go"}))
+            .await
+            .unwrap();
+        gitbase
+            .create_index("notes", IndexSpec::new("by_tag", "tag"))
+            .await
+            .unwrap();
+        let reports = gitbase.verify_indexes("notes").await.unwrap();
+        assert!(reports[0].is_clean());
+
+        // Edits made by hand, bypassing the index
+        let edited = DocumentFile::new(&rust.id, json!({"tag": "systems"}));
+        storage
+            .write(
+                &document_path("notes", &rust.id),
+                &edited.render(Default::default()).unwrap(),
+            )
+            .await
+            .unwrap();
+        let added = DocumentFile::new("added", json!({"tag": "go"}));
+        storage
+            .write(
+                &document_path("notes", "added"),
+                &added.render(Default::default()).unwrap(),
+            )
+            .await
+            .unwrap();
+        storage
+            .delete(&document_path("notes", &go.id))
+            .await
+            .unwrap();
+
+        let reports = gitbase.verify_indexes("notes").await.unwrap();
+        assert_eq!(
+            reports,
+            vec![IndexReport {
+                index: "by_tag".to_string(),
+                missing: vec![
+                    (json!("go"), "added".to_string()),
+                    (json!("systems"), rust.id.clone())
+                ],
+                stale: vec![(json!("rust"), rust.id.clone())],
+                dangling: vec![go.id.clone()],
+            }]
+        );
+
+        let index = gitbase.rebuild_index("notes", "by_tag").await.unwrap();
+        assert_eq!(index.lookup(&json!("go")), ["added"]);
+        assert_eq!(index.lookup(&json!("systems")), [rust.id.as_str()]);
+        assert_eq!(index.entries.len(), 2);
+        assert_eq!(gitbase.get_index("notes", "by_tag").await.unwrap(), index);
+        let reports = gitbase.verify_indexes("notes").await.unwrap();
+        assert!(reports[0].is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_replace_unparsable_document() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let mut ids = Vec::new();
+        for tag in ["rust", "go"] {
+            let doc = gitbase
+                .insert_document("notes", json!({"tag": tag}))
+                .await
+                .unwrap();
+            ids.push(doc.id);
+        }
+        gitbase
+            .create_index("notes", IndexSpec::new("by_tag", "tag"))
+            .await
+            .unwrap();
+
+        // Both documents broken by hand, then one repaired and the other deleted
+        let paths: Vec<_> = ids.iter().map(|id| document_path("notes", id)).collect();
+        for path in &paths {
+            storage.write(path, "not json").await.unwrap();
+        }
+        let repaired = DocumentFile::new(&ids[0], json!({"tag": "systems"}));
+        let mut tx = gitbase.transaction();
+        tx.stage(Change::write(
+            &paths[0],
+            repaired.render(Default::default()).unwrap(),
+        ));
+        tx.stage(Change::delete(&paths[1]));
+        tx.commit("Repair notes").await.unwrap();
+
+        let index = gitbase.get_index("notes", "by_tag").await.unwrap();
+        assert_eq!(index.lookup(&json!("systems")), [ids[0].as_str()]);
+        assert_eq!(index.entries.len(), 1);
+        let reports = gitbase.verify_indexes("notes").await.unwrap();
+        assert!(reports[0].is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_unique_index() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("users").await.unwrap();
+        let ada = gitbase
+            .insert_document("users", json!({"email": "ada@example.com"}))
+            .await
+            .unwrap();
+        gitbase
+            .create_index("users", IndexSpec::new("by_email", "email").unique())
+            .await
+            .unwrap();
+
+        let is_violation = |err: &Report<GBError>| {
+            matches!(
+                err.current_context(),
+                GBError::Index(IndexError::UniqueViolation { doc_id, .. }) if *doc_id == ada.id
+            )
+        };
+
+        let err = gitbase
+            .insert_document("users", json!({"email": "ada@example.com"}))
+            .await
+            .unwrap_err();
+        assert!(is_violation(&err));
+        assert_eq!(gitbase.list_documents("users").await.unwrap().len(), 1);
+
+        let grace = gitbase
+            .insert_document("users", json!({"email": "grace@example.com"}))
+            .await
+            .unwrap();
+        let err = gitbase
+            .update_document("users", &grace.id, json!({"email": "ada@example.com"}))
+            .await
+            .unwrap_err();
+        assert!(is_violation(&err));
+
+        // Updating a document without changing its key is fine
+        gitbase
+            .update_document("users", &ada.id, json!({"name": "Ada"}))
+            .await
+            .unwrap();
+
+        let mut tx = gitbase.transaction();
+        tx.insert("users", json!({"email": "alan@example.com"}))
+            .unwrap();
+        tx.insert("users", json!({"email": "alan@example.com"}))
+            .unwrap();
+        let err = tx.commit("Add Alan twice").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Index(IndexError::UniqueViolation { .. })
+        ));
+
+        // Documents without the field are not indexed, so they never collide
+        gitbase
+            .create_index("users", IndexSpec::new("by_domain", "domain").unique())
+            .await
+            .unwrap();
+        gitbase
+            .insert_document(
+                "users",
+                json!({"email": "x@example.com", "domain": "example.com"}),
+            )
+            .await
+            .unwrap();
+        let err = gitbase
+            .update_document("users", &ada.id, json!({"domain": "example.com"}))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Index(IndexError::UniqueViolation { index, .. }) if index == "by_domain"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_compound_index() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("tasks").await.unwrap();
+        gitbase
+            .create_index(
+                "tasks",
+                IndexSpec::compound("by_owner_status", &["owner", "status"]),
+            )
+            .await
+            .unwrap();
+
+        let mut ids = Vec::new();
+        for (owner, status) in [("ada", "open"), ("ada", "done"), ("grace", "open")] {
+            let content = json!({"owner": owner, "status": status});
+            ids.push(gitbase.insert_document("tasks", content).await.unwrap().id);
+        }
+
+        let index = gitbase.get_index("tasks", "by_owner_status").await.unwrap();
+        assert_eq!(index.field(), None);
+        let key = index.key(&[json!("ada"), json!("open")]).unwrap();
+        assert_eq!(index.lookup(&key), [ids[0].as_str()]);
+
+        // Only the candidate from the compound index is read, the corrupt one never is
+        storage
+            .write(
+                &crate::document::document_path("tasks", &ids[2]),
+                "not json",
+            )
+            .await
+            .unwrap();
+        let filter = crate::Filter::eq("owner", json!("ada"))
+            .and(crate::Filter::eq("status", json!("done")));
+        let found = gitbase.query_documents("tasks", &filter).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, ids[1]);
+    }
+}
@@ -1,105 +1,267 @@
 #![allow(dead_code)]
 
+mod cache;
 mod coder;
-mod error;
-mod storage;
+mod collection;
+mod config;
+mod database;
+mod document;
+pub mod error;
+mod index;
+mod migration;
+mod query;
+mod schema;
+mod search;
+pub mod storage;
+mod transaction;
 
-use anyhow::{Ok, Result};
-use lru::LruCache;
-use octocrab::models::repos::Content;
-use octocrab::Octocrab;
+use document::DocumentFile;
+use error::{AppResult, IntoAppResult};
 use serde_json::Value;
-use std::{num::NonZeroUsize, sync::Arc};
-use tokio::sync::Mutex;
+use std::num::NonZeroUsize;
+use std::{fmt, sync::Arc};
+use storage::{
+    git_blob_sha, CachedStorage, Change, GitHubStorage, ListOptions, StorageBackend, WritePolicy,
+    DEFAULT_BRANCH,
+};
+
+pub use cache::CacheStats;
+pub use collection::Collection;
+pub use config::{Author, Config, DocumentFormat, IdScheme};
+pub use index::{Index, IndexEntry, IndexKind, IndexReport, IndexSpec};
+pub use migration::{FieldType, Migration, Transform};
+pub use query::{Filter, Query, QueryPage, SortKey, SortOrder};
+pub use search::SearchHit;
+pub use transaction::Transaction;
 
-#[derive(Debug)]
 pub struct GitBase {
-    client: Arc<Octocrab>,
-    cache: Arc<Mutex<LruCache<String, (String, String)>>>,
-    owner: String,
-    repo: String,
+    /// The backend behind `cache`, so every read and write goes through it
+    storage: Arc<dyn StorageBackend>,
+    cache: CachedStorage<dyn StorageBackend>,
+    config: Config,
+    /// Branch the storage reads from and commits to
+    branch: String,
+}
+
+impl fmt::Debug for GitBase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GitBase").finish_non_exhaustive()
+    }
 }
 
 impl GitBase {
-    /// 创建 GitBase 实例，初始化缓存和 GitHub 客户端
-    pub fn new(token: &str, owner: &str, repo: &str) -> Arc<Self> {
-        let client = Arc::new(
-            Octocrab::builder()
-                .personal_token(token.to_string())
-                .build()
-                .unwrap(),
-        );
+    /// 创建基于 GitHub 仓库的 GitBase 实例：从仓库的默认分支读取 `.gitbase/config.json`，
+    /// 按其中的分支、提交身份、ID 方案、文档格式和缓存大小打开数据库
+    pub async fn new(token: &str, owner: &str, repo: &str) -> AppResult<Arc<Self>> {
+        let storage = GitHubStorage::new(token, owner, repo, None).into_app()?;
+
+        Self::open_github(storage).await
+    }
+
+    /// 与 [`GitBase::new`] 相同，但使用已配置好的存储（如自定义客户端）；
+    /// 存储自身的分支会被仓库默认分支或配置中的分支取代
+    pub async fn open_github(storage: GitHubStorage) -> AppResult<Arc<Self>> {
+        let branch = storage.default_branch().await.into_app()?;
+        let storage = storage.with_branch(&branch);
+
+        // 配置未指定分支时留在仓库的默认分支上
+        let config = Config::load(&storage).await?;
+        let branch = config.default_branch.clone().unwrap_or(branch);
+        let mut storage = storage.with_branch(&branch);
+        if let Some(author) = &config.author {
+            storage = storage.with_signature(&author.name, &author.email);
+        }
+
+        Ok(Self::on_branch(
+            cached(storage, &config).into_dyn(),
+            config,
+            branch,
+        ))
+    }
+
+    /// 使用任意存储后端和默认配置创建 GitBase 实例
+    pub fn with_storage(storage: impl StorageBackend + 'static) -> Arc<Self> {
+        Self::with_config(storage, Config::default())
+    }
 
+    /// 使用存储后端中 `.gitbase/config.json` 的配置创建 GitBase 实例；
+    /// 分支和提交身份由存储后端自身决定
+    pub async fn open(storage: impl StorageBackend + 'static) -> AppResult<Arc<Self>> {
+        let config = Config::load(&storage).await?;
+
+        Ok(Self::with_config(storage, config))
+    }
+
+    /// 使用任意存储后端和给定配置创建 GitBase 实例，初始化缓存
+    pub fn with_config(storage: impl StorageBackend + 'static, config: Config) -> Arc<Self> {
+        let storage = cached(storage, &config);
+
+        Self::with_cache(storage, config)
+    }
+
+    /// 使用已包装缓存的存储后端创建 GitBase 实例，可自行设置缓存的 TTL 和写入策略；
+    /// 配置中的缓存大小不再生效
+    pub fn with_cache<B: StorageBackend + 'static>(
+        storage: CachedStorage<B>,
+        config: Config,
+    ) -> Arc<Self> {
+        let branch = config
+            .default_branch
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BRANCH.to_string());
+
+        Self::on_branch(storage.into_dyn(), config, branch)
+    }
+
+    /// An instance whose storage is on `branch`, whatever the configuration says
+    pub(crate) fn on_branch(
+        cache: CachedStorage<dyn StorageBackend>,
+        config: Config,
+        branch: String,
+    ) -> Arc<Self> {
         Arc::new(Self {
-            client,
-            cache: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(100).unwrap()))),
-            owner: owner.to_string(),
-            repo: repo.to_string(),
+            storage: Arc::new(cache.clone()),
+            cache,
+            config,
+            branch,
         })
     }
 
-    /// 从 GitHub 仓库中获取文件内容
-    pub async fn fetch_file(&self, path: &str, branch: &str) -> Result<Content> {
-        let mut contents = self
-            .client
-            .repos(&self.owner, &self.repo)
-            .get_content()
-            .path(path)
-            .r#ref(branch)
-            .send()
-            .await
-            .unwrap();
-        let contents = contents.take_items();
-        let content = &contents[0];
+    /// 从存储后端中获取文件内容
+    pub async fn fetch_file(&self, path: &str) -> AppResult<String> {
+        self.storage.read(path).await.into_app()
+    }
 
-        Ok(content.clone())
+    /// 开始一个事务，暂存的所有修改在提交时合并为一次 Git commit
+    pub fn transaction(&self) -> Transaction<'_> {
+        Transaction::new(self)
     }
 
-    pub async fn create_collection(&self, name: &str, branch: &str) -> Result<()> {
-        let collection_id = coder::generate_collection_id(name).unwrap();
-        let dir_path = format!("collections/{}", name);
+    /// 在集合中插入新文档，ID 由内容和时间戳生成；文档与索引的修改在同一个 commit 中提交
+    pub async fn insert_document(&self, collection: &str, content: Value) -> AppResult<Document> {
+        let doc_id = document::new_document_id(&content, self.config.id_scheme)?;
+        let path = document::document_path(collection, &doc_id);
+        let file = DocumentFile::new(&doc_id, content);
+        let raw = file.render(self.config.document_format)?;
 
-        // 1. 在集合目录下创建 `.gitkeep` 文件，让 Git 识别目录
-        let gitkeep_path = format!("{}/.gitkeep", dir_path);
-        self.client
-            .repos(&self.owner, &self.repo)
-            .create_file(gitkeep_path, "Initialize collection directory", "")
-            .branch(branch)
-            .send()
+        let mut tx = self.transaction();
+        tx.stage(Change::write(path, raw.as_str()));
+        tx.commit(&format!("Insert document {} into {}", doc_id, collection))
             .await?;
 
-        // 2. 在集合目录下创建 `collection.json`，存储唯一 ID
-        let metadata_path = format!("{}/collection.json", dir_path);
-        let metadata_content = serde_json::json!({
-            "name": name,
-            "collection_id": collection_id,
-            "created_at": chrono::Utc::now().to_rfc3339(),
-        })
-        .to_string();
-
-        self.client
-            .repos(&self.owner, &self.repo)
-            .create_file(
-                metadata_path,
-                "Store collection metadata",
-                &metadata_content,
+        Ok(file.into_document(git_blob_sha(raw.as_bytes())))
+    }
+
+    /// 列出集合中所有文档的 ID
+    pub async fn list_documents(&self, collection: &str) -> AppResult<Vec<String>> {
+        let page = self
+            .storage
+            .list(
+                &document::collection_dir(collection),
+                &ListOptions::default(),
             )
-            .branch(branch)
-            .send()
+            .await
+            .into_app()?;
+
+        let ids = page
+            .entries
+            .iter()
+            .filter(|entry| entry.is_file())
+            .filter_map(|entry| document::parse_document_path(&entry.path))
+            .map(|(_, doc_id)| doc_id.to_string())
+            .collect();
+
+        Ok(ids)
+    }
+
+    /// 读取文档，`meta.updated_sha` 为文件的 blob SHA；重复读取由缓存提供，
+    /// 本地写入会使缓存失效
+    pub async fn get_document(&self, collection: &str, doc_id: &str) -> AppResult<Document> {
+        let path = document::document_path(collection, doc_id);
+        let raw = self.storage.read(&path).await.into_app()?;
+        let sha = git_blob_sha(raw.as_bytes());
+
+        Ok(DocumentFile::parse(&raw)?.into_document(sha))
+    }
+
+    /// The document as currently stored, bypassing the cache
+    pub(crate) async fn read_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+    ) -> AppResult<Document> {
+        let path = document::document_path(collection, doc_id);
+        let raw = self.cache.inner().read(&path).await.into_app()?;
+        let sha = git_blob_sha(raw.as_bytes());
+
+        Ok(DocumentFile::parse(&raw)?.into_document(sha))
+    }
+
+    /// 以 JSON Merge Patch 更新文档内容；读取后文档若被他人修改则返回 `StorageError::Conflict`
+    pub async fn update_document(
+        &self,
+        collection: &str,
+        doc_id: &str,
+        patch: Value,
+    ) -> AppResult<Document> {
+        let mut document = self.get_document(collection, doc_id).await?;
+        document::merge_patch(&mut document.content, &patch);
+
+        self.replace_document(collection, &document).await
+    }
+
+    /// 用 `document.content` 整体替换文档内容，以 `document.meta.updated_sha` 作为前置条件
+    pub async fn replace_document(
+        &self,
+        collection: &str,
+        document: &Document,
+    ) -> AppResult<Document> {
+        let path = document::document_path(collection, &document.id);
+        let file = DocumentFile {
+            id: document.id.clone(),
+            created_at: document.meta.created_at.clone(),
+            content: document.content.clone(),
+        };
+
+        let raw = file.render(self.config.document_format)?;
+
+        let mut tx = self.transaction();
+        tx.stage(Change::write(path, raw.as_str()).expecting(document.meta.updated_sha.as_str()));
+        tx.commit(&format!(
+            "Update document {} in {}",
+            document.id, collection
+        ))
+        .await?;
+
+        Ok(file.into_document(git_blob_sha(raw.as_bytes())))
+    }
+
+    /// 删除文档
+    pub async fn delete_document(&self, collection: &str, doc_id: &str) -> AppResult<()> {
+        let mut tx = self.transaction();
+        tx.delete(collection, doc_id).await?;
+        tx.commit(&format!("Delete document {} from {}", doc_id, collection))
             .await?;
 
         Ok(())
     }
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// `storage` behind a read cache holding `config.cache_size` files. Writes drop the cached file
+/// rather than storing it, so the next read fetches it along with its ETag.
+pub(crate) fn cached<B: StorageBackend>(storage: B, config: &Config) -> CachedStorage<B> {
+    let capacity = NonZeroUsize::new(config.cache_size).unwrap_or(NonZeroUsize::MIN);
+    CachedStorage::with_capacity(storage, capacity).with_policy(WritePolicy::WriteAround)
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Document {
     pub id: String,
     pub content: Value,
     pub meta: Metadata,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Metadata {
     pub created_at: String,
     pub updated_sha: String,
@@ -123,10 +285,8 @@ pub struct Metadata {
 //         let gitbase = init_gitbase();
 
 //         let content = gitbase
-//             .fetch_file("README.md", "main")
+//             .fetch_file("README.md")
 //             .await
-//             .unwrap()
-//             .decoded_content()
 //             .unwrap();
 //         println!("content: {:?}", content);
 //     }
@@ -135,6 +295,6 @@ pub struct Metadata {
 //     async fn test_create_collection() {
 //         let gitbase = init_gitbase();
 
-//         gitbase.create_collection("notes", "main").await.unwrap();
+//         gitbase.create_collection("notes").await.unwrap();
 //     }
 // }
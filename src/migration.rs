@@ -0,0 +1,449 @@
+use error_stack::Report;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+
+use crate::collection::{collection_meta_path, Collection};
+use crate::document::{document_path, field_value, DocumentFile};
+use crate::error::{AppResult, GBError, MigrationError};
+use crate::schema::{check, compile, schema_path};
+use crate::storage::Change;
+use crate::GitBase;
+
+/// One declarative change to the shape of every document. Fields are dotted paths such as
+/// `author.name`; intermediate objects are created as needed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Transform {
+    /// Set `field` to `default` in documents that do not have it
+    AddField {
+        field: String,
+        default: Value,
+    },
+    /// Move the value of `from` to `to`, replacing any value there
+    RenameField {
+        from: String,
+        to: String,
+    },
+    DropField {
+        field: String,
+    },
+    /// Convert the value of `field` to `to`, failing the migration if a value cannot be.
+    /// `null` is left as it is.
+    ChangeType {
+        field: String,
+        to: FieldType,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    /// Wrap a single value into a one-element array
+    Array,
+}
+
+/// The transforms that take a collection to schema `version`, passed to [`GitBase::migrate`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Migration {
+    pub version: u32,
+    pub transforms: Vec<Transform>,
+    /// JSON Schema the migrated documents must match, replacing the collection's schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+}
+
+impl Migration {
+    pub fn new(version: u32) -> Self {
+        Migration {
+            version,
+            transforms: Vec::new(),
+            schema: None,
+        }
+    }
+
+    pub fn add_field(mut self, field: &str, default: Value) -> Self {
+        self.transforms.push(Transform::AddField {
+            field: field.to_string(),
+            default,
+        });
+        self
+    }
+
+    pub fn rename_field(mut self, from: &str, to: &str) -> Self {
+        self.transforms.push(Transform::RenameField {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        self
+    }
+
+    pub fn drop_field(mut self, field: &str) -> Self {
+        self.transforms.push(Transform::DropField {
+            field: field.to_string(),
+        });
+        self
+    }
+
+    pub fn change_type(mut self, field: &str, to: FieldType) -> Self {
+        self.transforms.push(Transform::ChangeType {
+            field: field.to_string(),
+            to,
+        });
+        self
+    }
+
+    pub fn schema(mut self, schema: Value) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+}
+
+impl Transform {
+    fn apply(&self, doc_id: &str, content: &mut Value) -> AppResult<()> {
+        match self {
+            Transform::AddField { field, default } => {
+                if field_value(content, field).is_none() {
+                    set_field(content, field, default.clone())
+                        .map_err(|reason| conversion(doc_id, field, reason))?;
+                }
+            }
+            Transform::RenameField { from, to } => {
+                if let Some(value) = take_field(content, from) {
+                    set_field(content, to, value)
+                        .map_err(|reason| conversion(doc_id, to, reason))?;
+                }
+            }
+            Transform::DropField { field } => {
+                take_field(content, field);
+            }
+            Transform::ChangeType { field, to } => {
+                if let Some(value) = take_field(content, field) {
+                    let converted =
+                        convert(&value, *to).map_err(|reason| conversion(doc_id, field, reason))?;
+                    set_field(content, field, converted)
+                        .map_err(|reason| conversion(doc_id, field, reason))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn conversion(doc_id: &str, field: &str, reason: String) -> Report<GBError> {
+    Report::new(GBError::Migration(MigrationError::Conversion {
+        doc_id: doc_id.to_string(),
+        field: field.to_string(),
+        reason,
+    }))
+}
+
+/// Remove the value at the dotted path `field` of an object tree
+fn take_field(content: &mut Value, field: &str) -> Option<Value> {
+    let (parent, key) = match field.rsplit_once('.') {
+        Some((parent, key)) => (
+            parent
+                .split('.')
+                .try_fold(content, |value, key| value.as_object_mut()?.get_mut(key))?,
+            key,
+        ),
+        None => (content, field),
+    };
+    parent.as_object_mut()?.remove(key)
+}
+
+/// Set the value at the dotted path `field`, replacing scalars along the way with objects.
+/// Fails rather than replace an array, which would lose its items.
+fn set_field(content: &mut Value, field: &str, value: Value) -> Result<(), String> {
+    let mut target = content;
+    for key in field.split('.') {
+        if target.is_array() {
+            return Err(format!("cannot set {} inside an array", field));
+        }
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        target = target
+            .as_object_mut()
+            .map(|map| map.entry(key).or_insert(Value::Null))
+            .expect("target is an object");
+    }
+    *target = value;
+    Ok(())
+}
+
+fn convert(value: &Value, to: FieldType) -> Result<Value, String> {
+    let fail = || Err(format!("{} is not convertible to {:?}", value, to));
+    let converted = match (to, value) {
+        (_, Value::Null) => Value::Null,
+        (FieldType::String, Value::String(_)) => value.clone(),
+        (FieldType::String, _) => Value::String(value.to_string()),
+        (FieldType::Number, Value::Number(_)) => value.clone(),
+        (FieldType::Number | FieldType::Integer, Value::Bool(flag)) => Value::from(*flag as i64),
+        (FieldType::Number, Value::String(text)) => match text.trim().parse::<Number>() {
+            Ok(number) => Value::Number(number),
+            Err(_) => return fail(),
+        },
+        (FieldType::Integer, Value::Number(number)) => match number.as_i64() {
+            Some(int) => Value::from(int),
+            None => match number.as_f64() {
+                Some(float) if float.fract() == 0.0 && float.abs() < i64::MAX as f64 => {
+                    Value::from(float as i64)
+                }
+                _ => return fail(),
+            },
+        },
+        (FieldType::Integer, Value::String(text)) => match text.trim().parse::<i64>() {
+            Ok(int) => Value::from(int),
+            Err(_) => return fail(),
+        },
+        (FieldType::Boolean, Value::Bool(_)) => value.clone(),
+        (FieldType::Boolean, Value::String(text)) => match text.trim() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => return fail(),
+        },
+        (FieldType::Boolean, Value::Number(number)) => Value::Bool(number.as_f64() != Some(0.0)),
+        (FieldType::Array, Value::Array(_)) => value.clone(),
+        (FieldType::Array, _) => Value::Array(vec![value.clone()]),
+        _ => return fail(),
+    };
+    Ok(converted)
+}
+
+impl GitBase {
+    /// 依次应用版本高于集合当前 `schema_version` 的迁移，改写所有文档并更新
+    /// `collection.json` 中的版本，全部在一个 commit 中完成；任一文档转换失败则不写入任何内容
+    pub async fn migrate(
+        &self,
+        collection: &str,
+        migrations: &[Migration],
+    ) -> AppResult<Collection> {
+        let (mut meta, meta_sha) = self.load_collection(collection).await?;
+
+        let pending: Vec<&Migration> = migrations
+            .iter()
+            .filter(|migration| migration.version > meta.schema_version)
+            .collect();
+        if pending
+            .windows(2)
+            .any(|pair| pair[0].version >= pair[1].version)
+        {
+            return Err(Report::new(GBError::Migration(
+                MigrationError::InvalidMigration(
+                    "Migration versions must be strictly increasing".to_string(),
+                ),
+            )));
+        }
+        let Some(last) = pending.last() else {
+            return Ok(meta);
+        };
+
+        // 最后一个带 schema 的迁移决定集合的新 schema；先编译，所有文档（包括未改变的）都按它校验
+        let schema = pending.iter().rev().find_map(|m| m.schema.as_ref());
+        let validator = schema
+            .map(|schema| compile(collection, schema))
+            .transpose()?;
+
+        let mut tx = self.transaction();
+        for doc_id in self.list_documents(collection).await? {
+            let document = self.read_document(collection, &doc_id).await?;
+            let mut content = document.content.clone();
+            for transform in pending.iter().flat_map(|migration| &migration.transforms) {
+                transform.apply(&doc_id, &mut content)?;
+            }
+            if let Some(validator) = &validator {
+                check(validator, collection, &doc_id, &content)?;
+            }
+            if content == document.content {
+                continue;
+            }
+
+            let file = DocumentFile {
+                id: document.id,
+                created_at: document.meta.created_at,
+                content,
+            };
+            tx.stage(
+                Change::write(
+                    document_path(collection, &doc_id),
+                    file.render(self.config.document_format)?,
+                )
+                .expecting(document.meta.updated_sha),
+            );
+        }
+
+        if let Some(schema) = schema {
+            let mut raw = serde_json::to_string_pretty(schema).map_err(GBError::from)?;
+            raw.push('\n');
+            tx.stage(Change::write(schema_path(collection), raw));
+        }
+
+        meta.schema_version = last.version;
+        tx.stage(
+            Change::write(collection_meta_path(collection), meta.render()?).expecting(meta_sha),
+        );
+        tx.commit(&format!(
+            "Migrate {} to schema version {}",
+            collection, last.version
+        ))
+        .await?;
+
+        Ok(meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SchemaError;
+    use crate::storage::InMemoryStorage;
+    use serde_json::json;
+
+    #[test]
+    fn test_transforms() {
+        let mut doc = json!({"title": "Rust", "meta": {"stars": "42"}, "draft": 1});
+        let migration = Migration::new(1)
+            .add_field("tags", json!([]))
+            .add_field("title", json!("untitled"))
+            .rename_field("meta.stars", "stats.stars")
+            .change_type("stats.stars", FieldType::Integer)
+            .change_type("draft", FieldType::Boolean)
+            .change_type("title", FieldType::Array)
+            .drop_field("meta");
+        for transform in &migration.transforms {
+            transform.apply("doc", &mut doc).unwrap();
+        }
+        assert_eq!(
+            doc,
+            json!({"title": ["Rust"], "tags": [], "stats": {"stars": 42}, "draft": true})
+        );
+
+        let err = Transform::ChangeType {
+            field: "title".to_string(),
+            to: FieldType::Number,
+        }
+        .apply("doc", &mut json!({"title": "Rust"}))
+        .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Migration(MigrationError::Conversion { .. })
+        ));
+
+        // Setting a field inside an array would replace the array
+        let mut doc = json!({"tags": ["lang"]});
+        let err = Transform::AddField {
+            field: "tags.first".to_string(),
+            default: json!("rust"),
+        }
+        .apply("doc", &mut doc)
+        .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Migration(MigrationError::Conversion { .. })
+        ));
+        assert_eq!(doc, json!({"tags": ["lang"]}));
+
+        let raw = r#"{"op": "rename_field", "from": "a", "to": "b"}"#;
+        let transform: Transform = serde_json::from_str(raw).unwrap();
+        assert_eq!(
+            transform,
+            Migration::new(1).rename_field("a", "b").transforms[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let doc = gitbase
+            .insert_document("notes", json!({"name": "Rust", "stars": "42"}))
+            .await
+            .unwrap();
+        let untouched = gitbase
+            .insert_document("notes", json!({"title": "Go", "stars": 7, "tags": []}))
+            .await
+            .unwrap();
+
+        let migrations = [
+            Migration::new(1).rename_field("name", "title"),
+            Migration::new(2)
+                .change_type("stars", FieldType::Integer)
+                .add_field("tags", json!([]))
+                .schema(json!({"required": ["title", "tags"]})),
+        ];
+        let collection = gitbase.migrate("notes", &migrations).await.unwrap();
+        assert_eq!(collection.schema_version, 2);
+        assert_eq!(gitbase.get_collection("notes").await.unwrap(), collection);
+        assert_eq!(
+            gitbase
+                .get_document("notes", &doc.id)
+                .await
+                .unwrap()
+                .content,
+            json!({"title": "Rust", "stars": 42, "tags": []})
+        );
+        let current = gitbase.get_document("notes", &untouched.id).await.unwrap();
+        assert_eq!(current.meta.updated_sha, untouched.meta.updated_sha);
+
+        // Applied migrations are skipped
+        let again = gitbase.migrate("notes", &migrations).await.unwrap();
+        assert_eq!(again, collection);
+
+        // A migration breaking the schema is rejected as a whole
+        let err = gitbase
+            .migrate("notes", &[Migration::new(3).drop_field("tags")])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Schema(SchemaError::Validation { .. })
+        ));
+        assert_eq!(
+            gitbase
+                .get_collection("notes")
+                .await
+                .unwrap()
+                .schema_version,
+            2
+        );
+
+        // The new schema is compiled and checked against unchanged documents too
+        let err = gitbase
+            .migrate("notes", &[Migration::new(3).schema(json!({"type": 42}))])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Schema(SchemaError::InvalidSchema { .. })
+        ));
+        let err = gitbase
+            .migrate(
+                "notes",
+                &[Migration::new(3).schema(json!({"required": ["summary"]}))],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Schema(SchemaError::Validation { .. })
+        ));
+        assert_eq!(
+            gitbase.get_schema("notes").await.unwrap(),
+            Some(json!({"required": ["title", "tags"]}))
+        );
+
+        let err = gitbase
+            .migrate("notes", &[Migration::new(4), Migration::new(3)])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Migration(MigrationError::InvalidMigration(_))
+        ));
+    }
+}
@@ -0,0 +1,878 @@
+use base64::{engine::general_purpose, Engine as _};
+use error_stack::Report;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::document::field_value;
+use crate::error::{AppResult, GBError, QueryError};
+use crate::index::{compare_values, Index, IndexKind};
+use crate::{Document, GitBase};
+
+/// A condition on a document's content. Fields are dotted paths such as `author.name`.
+///
+/// As in MongoDB, a condition on a field holding an array holds if any element satisfies it,
+/// and comparisons only match values of the same type: `{"$gt": 3}` never matches a string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Eq(String, Value),
+    Ne(String, Value),
+    Gt(String, Value),
+    Gte(String, Value),
+    Lt(String, Value),
+    Lte(String, Value),
+    In(String, Vec<Value>),
+    Exists(String, bool),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn eq(field: &str, value: Value) -> Self {
+        Filter::Eq(field.to_string(), value)
+    }
+
+    pub fn ne(field: &str, value: Value) -> Self {
+        Filter::Ne(field.to_string(), value)
+    }
+
+    pub fn gt(field: &str, value: Value) -> Self {
+        Filter::Gt(field.to_string(), value)
+    }
+
+    pub fn gte(field: &str, value: Value) -> Self {
+        Filter::Gte(field.to_string(), value)
+    }
+
+    pub fn lt(field: &str, value: Value) -> Self {
+        Filter::Lt(field.to_string(), value)
+    }
+
+    pub fn lte(field: &str, value: Value) -> Self {
+        Filter::Lte(field.to_string(), value)
+    }
+
+    pub fn is_in(field: &str, values: Vec<Value>) -> Self {
+        Filter::In(field.to_string(), values)
+    }
+
+    pub fn exists(field: &str, exists: bool) -> Self {
+        Filter::Exists(field.to_string(), exists)
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            filter => Filter::And(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            filter => Filter::Or(vec![filter, other]),
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Filter::Not(Box::new(self))
+    }
+
+    /// Parse a MongoDB-style filter document, e.g.
+    /// `{"tags": "rust", "stars": {"$gte": 10}, "$or": [{"draft": false}, {"author": {"$exists": true}}]}`.
+    ///
+    /// Supported operators are `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in`, `$nin`,
+    /// `$exists`, `$not`, `$and` and `$or`.
+    pub fn parse(filter: &Value) -> AppResult<Self> {
+        let Value::Object(conditions) = filter else {
+            return Err(invalid("A filter must be an object"));
+        };
+
+        let mut filters = Vec::with_capacity(conditions.len());
+        for (key, value) in conditions {
+            filters.push(match key.as_str() {
+                "$and" => Filter::And(parse_list(key, value)?),
+                "$or" => Filter::Or(parse_list(key, value)?),
+                "$not" => Filter::parse(value)?.not(),
+                key if key.starts_with('$') => {
+                    return Err(invalid(&format!("Unknown operator {}", key)))
+                }
+                field => parse_field(field, value)?,
+            });
+        }
+
+        Ok(match filters.len() {
+            1 => filters.remove(0),
+            _ => Filter::And(filters),
+        })
+    }
+
+    /// Whether a document with `content` satisfies the filter
+    pub fn matches(&self, content: &Value) -> bool {
+        match self {
+            Filter::Eq(field, value) => any_value(content, field, |v| v == value),
+            Filter::Ne(field, value) => !any_value(content, field, |v| v == value),
+            Filter::Gt(field, value) => compares(content, field, value, Ordering::is_gt),
+            Filter::Gte(field, value) => compares(content, field, value, Ordering::is_ge),
+            Filter::Lt(field, value) => compares(content, field, value, Ordering::is_lt),
+            Filter::Lte(field, value) => compares(content, field, value, Ordering::is_le),
+            Filter::In(field, values) => any_value(content, field, |v| values.contains(v)),
+            Filter::Exists(field, exists) => field_value(content, field).is_some() == *exists,
+            Filter::And(filters) => filters.iter().all(|filter| filter.matches(content)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches(content)),
+            Filter::Not(filter) => !filter.matches(content),
+        }
+    }
+
+    /// IDs of the documents that may match, narrowed down with the indexes on the fields the
+    /// filter uses, or `None` when the indexes cannot bound the result and a scan is needed.
+    /// Candidates are a superset of the matches; each is still checked with [`Filter::matches`].
+    fn candidates(&self, indexes: &Indexes) -> Option<BTreeSet<String>> {
+        match self {
+            Filter::Eq(field, value) if !value.is_array() => {
+                let index = indexes.by_field.get(field.as_str())?;
+                Some(index.lookup(value).iter().cloned().collect())
+            }
+            Filter::In(field, values) if !values.iter().any(Value::is_array) => {
+                let index = indexes.by_field.get(field.as_str())?;
+                let ids = values.iter().flat_map(|value| index.lookup(value));
+                Some(ids.cloned().collect())
+            }
+            Filter::Gt(field, value) => range(indexes, field, value, Ordering::is_gt),
+            Filter::Gte(field, value) => range(indexes, field, value, Ordering::is_ge),
+            Filter::Lt(field, value) => range(indexes, field, value, Ordering::is_lt),
+            Filter::Lte(field, value) => range(indexes, field, value, Ordering::is_le),
+            Filter::And(filters) => filters
+                .iter()
+                .filter_map(|filter| filter.candidates(indexes))
+                .chain(compound_candidates(filters, indexes))
+                .reduce(|a, b| a.intersection(&b).cloned().collect()),
+            Filter::Or(filters) => filters
+                .iter()
+                .map(|filter| filter.candidates(indexes))
+                .try_fold(BTreeSet::new(), |mut ids, candidates| {
+                    ids.extend(candidates?);
+                    Some(ids)
+                }),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(message: &str) -> Report<GBError> {
+    Report::new(GBError::Query(QueryError::InvalidFilter(
+        message.to_string(),
+    )))
+}
+
+fn parse_list(operator: &str, value: &Value) -> AppResult<Vec<Filter>> {
+    let Value::Array(filters) = value else {
+        return Err(invalid(&format!("{} takes an array of filters", operator)));
+    };
+
+    filters.iter().map(Filter::parse).collect()
+}
+
+fn parse_field(field: &str, condition: &Value) -> AppResult<Filter> {
+    // `{"field": value}` is equality, unless the object holds operators
+    let operators = match condition {
+        Value::Object(map) if !map.is_empty() && map.keys().all(|key| key.starts_with('$')) => map,
+        value => return Ok(Filter::eq(field, value.clone())),
+    };
+
+    let mut filters = Vec::with_capacity(operators.len());
+    for (operator, value) in operators {
+        filters.push(match operator.as_str() {
+            "$eq" => Filter::eq(field, value.clone()),
+            "$ne" => Filter::ne(field, value.clone()),
+            "$gt" => Filter::gt(field, value.clone()),
+            "$gte" => Filter::gte(field, value.clone()),
+            "$lt" => Filter::lt(field, value.clone()),
+            "$lte" => Filter::lte(field, value.clone()),
+            "$in" => Filter::is_in(field, parse_values(operator, value)?),
+            "$nin" => Filter::is_in(field, parse_values(operator, value)?).not(),
+            "$exists" => match value {
+                Value::Bool(exists) => Filter::exists(field, *exists),
+                _ => return Err(invalid("$exists takes a boolean")),
+            },
+            "$not" => {
+                let mut negated = Map::new();
+                negated.insert(field.to_string(), value.clone());
+                Filter::parse(&Value::Object(negated))?.not()
+            }
+            operator => return Err(invalid(&format!("Unknown operator {}", operator))),
+        });
+    }
+
+    Ok(match filters.len() {
+        1 => filters.remove(0),
+        _ => Filter::And(filters),
+    })
+}
+
+fn parse_values(operator: &str, value: &Value) -> AppResult<Vec<Value>> {
+    match value {
+        Value::Array(values) => Ok(values.clone()),
+        _ => Err(invalid(&format!("{} takes an array of values", operator))),
+    }
+}
+
+/// Whether the value at `field`, or any element of it when it is an array, satisfies `check`
+fn any_value(content: &Value, field: &str, check: impl Fn(&Value) -> bool) -> bool {
+    match field_value(content, field) {
+        Some(value @ Value::Array(items)) => check(value) || items.iter().any(check),
+        Some(value) => check(value),
+        None => false,
+    }
+}
+
+/// Order `a` against `b` if they are of the same comparable type
+fn compare_same_type(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(_), Value::Number(_))
+        | (Value::String(_), Value::String(_))
+        | (Value::Bool(_), Value::Bool(_)) => Some(compare_values(a, b)),
+        _ => None,
+    }
+}
+
+fn compares(content: &Value, field: &str, bound: &Value, check: fn(Ordering) -> bool) -> bool {
+    any_value(content, field, |value| {
+        compare_same_type(value, bound).is_some_and(check)
+    })
+}
+
+/// The indexes of a collection usable by a query
+struct Indexes<'a> {
+    /// Single-field indexes by field
+    by_field: HashMap<&'a str, &'a Index>,
+    compound: Vec<&'a Index>,
+}
+
+impl<'a> Indexes<'a> {
+    fn new(indexes: &'a [Index]) -> Self {
+        let (single, compound): (Vec<&Index>, Vec<&Index>) = indexes
+            .iter()
+            .filter(|index| index.kind == IndexKind::Value)
+            .partition(|index| index.field().is_some());
+        let by_field = single
+            .into_iter()
+            .filter_map(|index| Some((index.field()?, index)))
+            .collect();
+
+        Indexes { by_field, compound }
+    }
+}
+
+/// Candidates from every compound index whose fields are all pinned by equalities in `filters`
+fn compound_candidates(filters: &[Filter], indexes: &Indexes) -> Vec<BTreeSet<String>> {
+    let equalities: HashMap<&str, &Value> = filters
+        .iter()
+        .filter_map(|filter| match filter {
+            Filter::Eq(field, value) if !value.is_array() => Some((field.as_str(), value)),
+            _ => None,
+        })
+        .collect();
+
+    indexes
+        .compound
+        .iter()
+        .filter_map(|index| {
+            let values: Option<Vec<Value>> = index
+                .fields
+                .iter()
+                .map(|field| equalities.get(field.as_str()).map(|value| (*value).clone()))
+                .collect();
+            let key = index.key(&values?)?;
+            Some(index.lookup(&key).iter().cloned().collect())
+        })
+        .collect()
+}
+
+/// IDs indexed under values of `field` that compare to `bound` as `check` requires
+fn range(
+    indexes: &Indexes,
+    field: &str,
+    bound: &Value,
+    check: fn(Ordering) -> bool,
+) -> Option<BTreeSet<String>> {
+    let index = indexes.by_field.get(field)?;
+    let ids = index
+        .entries
+        .iter()
+        .filter(|entry| compare_same_type(&entry.value, bound).is_some_and(check))
+        .flat_map(|entry| entry.ids.iter().cloned());
+
+    Some(ids.collect())
+}
+
+/// Direction of a sort key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortKey {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+/// A filtered, sorted and paginated read of a collection.
+///
+/// Results are ordered by the sort keys in turn, then by document ID, so every document has a
+/// stable position. Documents missing a sort field come before all others in ascending order.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub filter: Option<Filter>,
+    pub sort: Vec<SortKey>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub cursor: Option<String>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn sort_by(mut self, field: &str, order: SortOrder) -> Self {
+        self.sort.push(SortKey {
+            field: field.to_string(),
+            order,
+        });
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip this many results, counted after the cursor
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Resume after the last document of a previous page, see [`QueryPage::next_cursor`]
+    pub fn after(mut self, cursor: impl Into<String>) -> Self {
+        self.cursor = Some(cursor.into());
+        self
+    }
+
+    fn sort_values(&self, document: &Document) -> Vec<Option<Value>> {
+        self.sort
+            .iter()
+            .map(|key| field_value(&document.content, &key.field).cloned())
+            .collect()
+    }
+
+    /// Order two positions, each given by its sort values and document ID
+    fn compare(&self, a: (&[Option<Value>], &str), b: (&[Option<Value>], &str)) -> Ordering {
+        self.sort
+            .iter()
+            .zip(a.0.iter().zip(b.0))
+            .map(|(key, (a, b))| {
+                let ordering = match (a, b) {
+                    (Some(a), Some(b)) => compare_values(a, b),
+                    (a, b) => a.is_some().cmp(&b.is_some()),
+                };
+                match key.order {
+                    SortOrder::Asc => ordering,
+                    SortOrder::Desc => ordering.reverse(),
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.1.cmp(b.1))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryPage {
+    pub documents: Vec<Document>,
+    /// Pass to [`Query::after`] to fetch the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Position of the last document of a page, serialized into an opaque cursor string. The sort
+/// keys are kept so a cursor cannot be replayed against a query ordered differently.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    sort: Vec<SortKey>,
+    values: Vec<Option<Value>>,
+    id: String,
+}
+
+impl Cursor {
+    fn encode(&self) -> AppResult<String> {
+        let raw = serde_json::to_vec(self).map_err(GBError::from)?;
+        Ok(general_purpose::URL_SAFE_NO_PAD.encode(raw))
+    }
+
+    fn decode(cursor: &str, query: &Query) -> AppResult<Self> {
+        let invalid = || {
+            Report::new(GBError::Query(QueryError::InvalidCursor(
+                cursor.to_string(),
+            )))
+        };
+
+        let raw = general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| invalid())?;
+        let decoded: Cursor = serde_json::from_slice(&raw).map_err(|_| invalid())?;
+        if decoded.sort != query.sort || decoded.values.len() != query.sort.len() {
+            return Err(
+                invalid().attach_printable("The cursor belongs to a differently sorted query")
+            );
+        }
+
+        Ok(decoded)
+    }
+}
+
+/// Document IDs whose order relative to other batches is final: every document of a batch
+/// sorts after those of earlier batches, so a query can stop reading once it has filled its
+/// page. IDs within a batch still need sorting.
+struct Batch {
+    /// The value of the first sort key shared by the whole batch, when known up front
+    key: Option<Value>,
+    ids: Vec<String>,
+}
+
+impl Batch {
+    /// Whether the whole batch sorts before the cursor position and can be skipped unread
+    fn before(&self, query: &Query, after: &Cursor) -> bool {
+        match (query.sort.first(), &self.key, after.values.first()) {
+            (None, _, _) => self.ids.iter().all(|id| id.as_str() <= after.id.as_str()),
+            (Some(first), Some(key), Some(Some(after))) => {
+                let ordering = compare_values(key, after);
+                match first.order {
+                    SortOrder::Asc => ordering.is_lt(),
+                    SortOrder::Desc => ordering.is_gt(),
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Split `doc_ids` into [`Batch`]es.
+///
+/// Without sort keys every document is its own batch, in ID order, which `list_documents` and
+/// candidate sets already follow. An index on the first sort key yields one batch per indexed
+/// value, but only if it covers the sort: every document must appear in it exactly once under a
+/// scalar value, which rules out collections where some documents lack the field or hold an
+/// array in it, even a one-element array, as arrays sort after every scalar.
+fn sorted_batches(query: &Query, index: Option<&Index>, doc_ids: Vec<String>) -> Vec<Batch> {
+    let Some(first) = query.sort.first() else {
+        let batches = doc_ids.into_iter().map(|id| Batch {
+            key: None,
+            ids: vec![id],
+        });
+        return batches.collect();
+    };
+
+    let covering = index.filter(|index| {
+        let indexed: usize = index.entries.iter().map(|entry| entry.ids.len()).sum();
+        let distinct: BTreeSet<&String> =
+            index.entries.iter().flat_map(|entry| &entry.ids).collect();
+        index.arrays.is_empty()
+            && indexed == doc_ids.len()
+            && distinct.len() == indexed
+            && doc_ids.iter().all(|id| distinct.contains(id))
+    });
+    let Some(index) = covering else {
+        return vec![Batch {
+            key: None,
+            ids: doc_ids,
+        }];
+    };
+
+    let batches = index.entries.iter().map(|entry| Batch {
+        key: Some(entry.value.clone()),
+        ids: entry.ids.clone(),
+    });
+    match first.order {
+        SortOrder::Asc => batches.collect(),
+        SortOrder::Desc => batches.rev().collect(),
+    }
+}
+
+impl GitBase {
+    /// 查询集合中满足 `filter` 的文档，按文档 ID 排序。
+    /// 过滤条件涉及的字段有索引时只读取候选文档，否则扫描整个集合。
+    pub async fn query_documents(
+        &self,
+        collection: &str,
+        filter: &Filter,
+    ) -> AppResult<Vec<Document>> {
+        let query = Query::new().filter(filter.clone());
+        Ok(self.find(collection, &query).await?.documents)
+    }
+
+    /// 按 `query` 过滤、排序并分页读取文档。
+    /// 第一个排序字段上的索引覆盖所有文档时按索引顺序读取，读满一页即停止。
+    pub async fn find(&self, collection: &str, query: &Query) -> AppResult<QueryPage> {
+        let after = match &query.cursor {
+            Some(cursor) => Some(Cursor::decode(cursor, query)?),
+            None => None,
+        };
+
+        let mut indexes = Vec::new();
+        for name in self.list_indexes(collection).await? {
+            indexes.push(self.get_index(collection, &name).await?);
+        }
+        let usable = Indexes::new(&indexes);
+
+        let candidates = query
+            .filter
+            .as_ref()
+            .and_then(|filter| filter.candidates(&usable));
+        let all_ids = self.list_documents(collection).await?;
+        let sort_index = query
+            .sort
+            .first()
+            .and_then(|key| usable.by_field.get(key.field.as_str()).copied());
+        let batches = match &candidates {
+            // Candidates narrow the reads more than any sort index could
+            Some(ids) if query.sort.is_empty() => {
+                sorted_batches(query, None, ids.iter().cloned().collect())
+            }
+            _ => sorted_batches(query, sort_index, all_ids),
+        };
+
+        // One result past the page tells whether there is a next page
+        let wanted = query.limit.map(|limit| query.offset + limit + 1);
+        let mut matched: Vec<(Vec<Option<Value>>, Document)> = Vec::new();
+        for batch in batches {
+            if after
+                .as_ref()
+                .is_some_and(|after| batch.before(query, after))
+            {
+                continue;
+            }
+
+            let mut found = Vec::new();
+            for doc_id in batch.ids {
+                if candidates
+                    .as_ref()
+                    .is_some_and(|ids| !ids.contains(&doc_id))
+                {
+                    continue;
+                }
+                let document = self.get_document(collection, &doc_id).await?;
+                if query
+                    .filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.matches(&document.content))
+                {
+                    found.push((query.sort_values(&document), document));
+                }
+            }
+
+            found.retain(|(values, document)| match &after {
+                Some(after) => {
+                    let position = (values.as_slice(), document.id.as_str());
+                    query.compare(position, (&after.values, &after.id)).is_gt()
+                }
+                None => true,
+            });
+            found.sort_by(|(a, x), (b, y)| query.compare((a, &x.id), (b, &y.id)));
+            matched.extend(found);
+
+            if wanted.is_some_and(|wanted| matched.len() >= wanted) {
+                break;
+            }
+        }
+
+        let mut page: Vec<_> = matched.into_iter().skip(query.offset).collect();
+        let next_cursor = match query.limit {
+            Some(limit) if page.len() > limit => {
+                page.truncate(limit);
+                let (values, last) = &page[limit - 1];
+                let cursor = Cursor {
+                    sort: query.sort.clone(),
+                    values: values.clone(),
+                    id: last.id.clone(),
+                };
+                Some(cursor.encode()?)
+            }
+            _ => None,
+        };
+
+        Ok(QueryPage {
+            documents: page.into_iter().map(|(_, document)| document).collect(),
+            next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexSpec;
+    use crate::storage::{InMemoryStorage, StorageBackend};
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_match() {
+        let doc = json!({
+            "title": "Rust",
+            "stars": 12,
+            "tags": ["lang", "systems"],
+            "author": {"name": "Ada"}
+        });
+
+        let cases = [
+            (json!({"title": "Rust"}), true),
+            (json!({"tags": "lang"}), true),
+            (json!({"tags": ["lang", "systems"]}), true),
+            (json!({"author.name": {"$in": ["Ada", "Grace"]}}), true),
+            (json!({"stars": {"$gte": 10, "$lt": 12}}), false),
+            (json!({"stars": {"$gt": "10"}}), false),
+            (json!({"author.email": {"$exists": false}}), true),
+            (
+                json!({"title": {"$ne": "Go"}, "tags": {"$nin": ["web"]}}),
+                true,
+            ),
+            (
+                json!({"$or": [{"stars": {"$lt": 5}}, {"title": "Rust"}]}),
+                true,
+            ),
+            (json!({"$not": {"title": "Rust"}}), false),
+            (json!({"stars": {"$not": {"$gt": 20}}}), true),
+        ];
+        for (filter, expected) in cases {
+            let parsed = Filter::parse(&filter).unwrap();
+            assert_eq!(parsed.matches(&doc), expected, "{}", filter);
+        }
+
+        for filter in [json!([]), json!({"$where": "1"}), json!({"a": {"$in": 1}})] {
+            let err = Filter::parse(&filter).unwrap_err();
+            assert!(matches!(
+                err.current_context(),
+                GBError::Query(QueryError::InvalidFilter(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_uses_indexes() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+
+        let mut ids = Vec::new();
+        for (title, stars) in [("a", 1), ("b", 5), ("c", 10)] {
+            let doc = gitbase
+                .insert_document("notes", json!({"title": title, "stars": stars}))
+                .await
+                .unwrap();
+            ids.push(doc.id);
+        }
+        gitbase
+            .create_index("notes", IndexSpec::new("by_stars", "stars"))
+            .await
+            .unwrap();
+
+        let filter = Filter::gte("stars", json!(5)).and(Filter::ne("title", json!("c")));
+        let found = gitbase.query_documents("notes", &filter).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].content["title"], "b");
+
+        // A corrupt document outside the index range is never read
+        let path = crate::document::document_path("notes", &ids[0]);
+        storage.write(&path, "not json").await.unwrap();
+        let found = gitbase
+            .query_documents("notes", &Filter::gt("stars", json!(1)))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+
+        // Without an index on `title` the collection is scanned
+        let err = gitbase
+            .query_documents("notes", &Filter::eq("title", json!("b")))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), GBError::Json(_)));
+    }
+
+    #[tokio::test]
+    async fn test_sort_and_paginate() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("tasks").await.unwrap();
+
+        let mut ids = Vec::new();
+        for (title, priority, done) in [
+            ("a", 2, false),
+            ("b", 1, true),
+            ("c", 2, true),
+            ("d", 3, false),
+            ("e", 1, false),
+        ] {
+            let content = json!({"title": title, "priority": priority, "done": done});
+            ids.push(gitbase.insert_document("tasks", content).await.unwrap().id);
+        }
+
+        let query = Query::new()
+            .sort_by("priority", SortOrder::Desc)
+            .sort_by("title", SortOrder::Asc)
+            .limit(2);
+        let mut titles = Vec::new();
+        let mut cursor = None;
+        loop {
+            let query = match cursor {
+                Some(cursor) => query.clone().after(cursor),
+                None => query.clone(),
+            };
+            let page = gitbase.find("tasks", &query).await.unwrap();
+            titles.extend(
+                page.documents
+                    .iter()
+                    .map(|doc| doc.content["title"].clone()),
+            );
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(titles, vec!["d", "a", "c", "b", "e"]);
+
+        let page = gitbase
+            .find(
+                "tasks",
+                &Query::new()
+                    .filter(Filter::eq("done", json!(false)))
+                    .sort_by("title", SortOrder::Desc)
+                    .offset(1)
+                    .limit(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.documents[0].content["title"], "d");
+        assert!(page.next_cursor.is_some());
+
+        let err = gitbase
+            .find("tasks", &Query::new().after("bm90IGEgY3Vyc29y"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Query(QueryError::InvalidCursor(_))
+        ));
+
+        // A covering index on the sort key lets the first page stop before the last document,
+        // which is corrupt and would fail the query if it were read
+        gitbase
+            .create_index("tasks", IndexSpec::new("by_priority", "priority"))
+            .await
+            .unwrap();
+        let by_priority = Query::new()
+            .sort_by("priority", SortOrder::Asc)
+            .sort_by("title", SortOrder::Asc);
+        let d_path = crate::document::document_path("tasks", &ids[3]);
+        let d_raw = storage.read(&d_path).await.unwrap();
+        storage.write(&d_path, "not json").await.unwrap();
+        let page = gitbase
+            .find("tasks", &by_priority.clone().limit(3))
+            .await
+            .unwrap();
+        let titles: Vec<_> = page
+            .documents
+            .iter()
+            .map(|doc| &doc.content["title"])
+            .collect();
+        assert_eq!(titles, vec!["b", "e", "a"]);
+
+        // Resuming skips the batches before the cursor without reading them. A fresh instance
+        // has nothing cached that could hide a read.
+        storage.write(&d_path, &d_raw).await.unwrap();
+        let b_path = crate::document::document_path("tasks", &ids[1]);
+        storage.write(&b_path, "not json").await.unwrap();
+        let page = GitBase::with_storage(storage.clone())
+            .find(
+                "tasks",
+                &by_priority.limit(1).after(page.next_cursor.unwrap()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.documents[0].content["title"], "c");
+    }
+
+    #[tokio::test]
+    async fn test_sort_by_array_field() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("items").await.unwrap();
+        for (name, n) in [("a", json!(3)), ("b", json!(1)), ("c", json!(7))] {
+            let content = json!({"name": name, "n": n});
+            gitbase.insert_document("items", content).await.unwrap();
+        }
+        let x = gitbase
+            .insert_document("items", json!({"name": "x", "n": 5}))
+            .await
+            .unwrap();
+        gitbase
+            .create_index("items", IndexSpec::new("by_n", "n"))
+            .await
+            .unwrap();
+
+        // `[5]` is indexed under 5 just like the scalar it replaces, but sorts after every number
+        gitbase
+            .update_document("items", &x.id, json!({"n": [5]}))
+            .await
+            .unwrap();
+        let query = Query::new().sort_by("n", SortOrder::Asc).limit(2);
+        let mut names = Vec::new();
+        let mut cursor = None;
+        loop {
+            let query = match cursor {
+                Some(cursor) => query.clone().after(cursor),
+                None => query.clone(),
+            };
+            let page = gitbase.find("items", &query).await.unwrap();
+            names.extend(page.documents.iter().map(|doc| doc.content["name"].clone()));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(names, vec!["b", "a", "c", "x"]);
+
+        // Back to a scalar, the index covers the sort again
+        gitbase
+            .update_document("items", &x.id, json!({"n": 5}))
+            .await
+            .unwrap();
+        let page = gitbase
+            .find("items", &Query::new().sort_by("n", SortOrder::Desc))
+            .await
+            .unwrap();
+        let names: Vec<_> = page
+            .documents
+            .iter()
+            .map(|doc| &doc.content["name"])
+            .collect();
+        assert_eq!(names, vec!["c", "x", "a", "b"]);
+    }
+}
@@ -0,0 +1,300 @@
+use error_stack::Report;
+use jsonschema::Validator;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::document::{parse_document_path, DocumentFile, GITBASE_DIR};
+use crate::error::{AppResult, GBError, IntoAppResult, SchemaError, StorageError, Violation};
+use crate::storage::Change;
+use crate::GitBase;
+
+pub const SCHEMAS_DIR: &str = "schemas";
+
+/// `.gitbase/schemas/<collection>.json`, the JSON Schema every document content of the
+/// collection must match
+pub fn schema_path(collection: &str) -> String {
+    format!("{}/{}/{}.json", GITBASE_DIR, SCHEMAS_DIR, collection)
+}
+
+pub(crate) fn compile(collection: &str, schema: &Value) -> AppResult<Validator> {
+    jsonschema::validator_for(schema).map_err(|e| {
+        Report::new(GBError::Schema(SchemaError::InvalidSchema {
+            collection: collection.to_string(),
+            reason: e.to_string(),
+        }))
+    })
+}
+
+fn parse(collection: &str, raw: &str) -> AppResult<Value> {
+    serde_json::from_str(raw).map_err(|e| {
+        Report::new(GBError::Schema(SchemaError::InvalidSchema {
+            collection: collection.to_string(),
+            reason: e.to_string(),
+        }))
+    })
+}
+
+/// Every constraint of `validator` that `content` fails
+fn violations(validator: &Validator, content: &Value) -> Vec<Violation> {
+    validator
+        .iter_errors(content)
+        .map(|error| Violation {
+            path: error.instance_path.to_string(),
+            schema_path: error.schema_path.to_string(),
+            message: error.to_string(),
+        })
+        .collect()
+}
+
+/// Fail with [`SchemaError::Validation`] listing the failing paths unless `content` matches
+pub(crate) fn check(
+    validator: &Validator,
+    collection: &str,
+    doc_id: &str,
+    content: &Value,
+) -> AppResult<()> {
+    let violations = violations(validator, content);
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    Err(Report::new(GBError::Schema(SchemaError::Validation {
+        collection: collection.to_string(),
+        doc_id: doc_id.to_string(),
+        violations,
+    })))
+}
+
+impl GitBase {
+    /// 读取集合的 JSON Schema，未定义时返回 `None`
+    pub async fn get_schema(&self, collection: &str) -> AppResult<Option<Value>> {
+        self.get_collection(collection).await?;
+
+        self.read_schema(collection).await
+    }
+
+    /// The stored schema of `collection`, without checking that the collection exists
+    async fn read_schema(&self, collection: &str) -> AppResult<Option<Value>> {
+        match self.storage.read(&schema_path(collection)).await {
+            Ok(raw) => Ok(Some(parse(collection, &raw)?)),
+            Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e).into_app(),
+        }
+    }
+
+    /// 设置集合的 JSON Schema，之后每次写入文档都会按它校验；不检查已有文档
+    pub async fn set_schema(&self, collection: &str, schema: Value) -> AppResult<()> {
+        self.get_collection(collection).await?;
+        compile(collection, &schema)?;
+
+        let mut raw = serde_json::to_string_pretty(&schema).map_err(GBError::from)?;
+        raw.push('\n');
+
+        let mut tx = self.transaction();
+        tx.stage(Change::write(schema_path(collection), raw));
+        tx.commit(&format!("Set schema of {}", collection)).await?;
+
+        Ok(())
+    }
+
+    /// 删除集合的 JSON Schema，之后不再校验文档；未定义 Schema 时不做任何修改
+    pub async fn drop_schema(&self, collection: &str) -> AppResult<()> {
+        self.get_collection(collection).await?;
+        if !self
+            .storage
+            .exists(&schema_path(collection))
+            .await
+            .into_app()?
+        {
+            return Ok(());
+        }
+
+        let mut tx = self.transaction();
+        tx.stage(Change::delete(schema_path(collection)));
+        tx.commit(&format!("Drop schema of {}", collection)).await?;
+
+        Ok(())
+    }
+
+    /// Check every document written by a batch against the schema of its collection, as staged
+    /// in the same batch or stored. Fails with [`SchemaError::Validation`] listing the failing
+    /// paths of the first document that does not match.
+    pub(crate) async fn validate_changes(&self, changes: &[Change]) -> AppResult<()> {
+        let mut written: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+        for change in changes {
+            if let Change::Write { path, content, .. } = change {
+                if let Some((collection, doc_id)) = parse_document_path(path) {
+                    written
+                        .entry(collection)
+                        .or_default()
+                        .push((doc_id, content));
+                }
+            }
+        }
+
+        for (collection, documents) in written {
+            let path = schema_path(collection);
+            let staged = changes.iter().rev().find(|change| change.path() == path);
+            let schema = match staged {
+                Some(Change::Write { content, .. }) => Some(parse(collection, content)?),
+                Some(Change::Delete { .. }) => None,
+                None => self.read_schema(collection).await?,
+            };
+            let Some(schema) = schema else {
+                continue;
+            };
+
+            let validator = compile(collection, &schema)?;
+            for (doc_id, raw) in documents {
+                let file = DocumentFile::parse(raw)?;
+                check(&validator, collection, doc_id, &file.content)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CollectionError;
+    use crate::storage::{InMemoryStorage, StorageBackend};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_schema_validation() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        assert_eq!(gitbase.get_schema("notes").await.unwrap(), None);
+
+        let schema = json!({
+            "type": "object",
+            "required": ["title"],
+            "properties": {
+                "title": {"type": "string"},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            }
+        });
+        gitbase.set_schema("notes", schema.clone()).await.unwrap();
+        assert_eq!(gitbase.get_schema("notes").await.unwrap(), Some(schema));
+
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Rust", "tags": ["lang"]}))
+            .await
+            .unwrap();
+
+        let err = gitbase
+            .insert_document("notes", json!({"tags": ["lang", 3]}))
+            .await
+            .unwrap_err();
+        let GBError::Schema(SchemaError::Validation { violations, .. }) = err.current_context()
+        else {
+            panic!("unexpected error: {:?}", err);
+        };
+        let mut paths: Vec<&str> = violations.iter().map(|v| v.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["", "/tags/1"]);
+        assert_eq!(gitbase.list_documents("notes").await.unwrap().len(), 1);
+
+        let err = gitbase
+            .update_document("notes", &doc.id, json!({"title": 42}))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Schema(SchemaError::Validation { violations, .. })
+                if violations[0].path == "/title"
+        ));
+        assert_eq!(
+            gitbase
+                .get_document("notes", &doc.id)
+                .await
+                .unwrap()
+                .content["title"],
+            "Rust"
+        );
+
+        let err = gitbase
+            .set_schema("notes", json!({"type": "no-such-type"}))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Schema(SchemaError::InvalidSchema { .. })
+        ));
+
+        gitbase.drop_schema("notes").await.unwrap();
+        gitbase
+            .update_document("notes", &doc.id, json!({"title": 42}))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_schema_follows_collection() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+        let schema = json!({"required": ["title"]});
+        gitbase.set_schema("notes", schema.clone()).await.unwrap();
+
+        gitbase.rename_collection("notes", "posts").await.unwrap();
+        let old_schema = gitbase.storage.exists(&schema_path("notes")).await;
+        assert!(!old_schema.unwrap());
+        assert_eq!(gitbase.get_schema("posts").await.unwrap(), Some(schema));
+        let err = gitbase
+            .insert_document("posts", json!({"tags": []}))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Schema(SchemaError::Validation { .. })
+        ));
+
+        // A collection created under a dropped name starts without a schema
+        gitbase.drop_collection("posts").await.unwrap();
+        gitbase.create_collection("posts").await.unwrap();
+        assert_eq!(gitbase.get_schema("posts").await.unwrap(), None);
+
+        let err = gitbase.drop_schema("notes").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Collection(CollectionError::NotFound(_))
+        ));
+        let err = gitbase.drop_schema("../config").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Collection(CollectionError::InvalidName(_))
+        ));
+        let err = gitbase.get_schema("notes").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Collection(CollectionError::NotFound(_))
+        ));
+        let err = gitbase.get_schema("../config").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Collection(CollectionError::InvalidName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_drop_missing_schema() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+
+        gitbase.drop_schema("notes").await.unwrap();
+        gitbase
+            .set_schema("notes", json!({"type": "object"}))
+            .await
+            .unwrap();
+        gitbase.drop_schema("notes").await.unwrap();
+        gitbase.drop_schema("notes").await.unwrap();
+        assert_eq!(gitbase.get_schema("notes").await.unwrap(), None);
+        assert!(!storage.exists(&schema_path("notes")).await.unwrap());
+    }
+}
@@ -0,0 +1,244 @@
+use error_stack::Report;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::error::{AppResult, GBError, IndexError};
+use crate::index::{Index, IndexKind};
+use crate::GitBase;
+
+/// BM25 term frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document length normalisation
+const B: f64 = 0.75;
+
+/// A document matching a [`GitBase::search`], with its relevance score
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub id: String,
+    pub score: f64,
+}
+
+/// Split `text` into lowercase words for a text index. Words are runs of letters and digits,
+/// except that every CJK character is a word of its own since those scripts do not separate
+/// words with spaces. Markdown link targets and HTML tags are skipped, so Markdown bodies are
+/// indexed by the text a reader sees.
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in strip_markup(text).chars() {
+        if c.is_alphanumeric() && !is_cjk(c) {
+            word.extend(c.to_lowercase());
+            continue;
+        }
+        if !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if is_cjk(c) {
+            words.push(c.to_string());
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}')
+}
+
+/// Replace the `(url)` of Markdown links and images and any HTML tag with a space
+fn strip_markup(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find(['<', ']']) {
+        stripped.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        let tag = tail[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        let end = if tail.starts_with("](") {
+            tail.find(')')
+        } else if tail.starts_with('<') && tag {
+            tail.find('>')
+        } else {
+            None
+        };
+
+        match end {
+            Some(end) => {
+                stripped.push(' ');
+                rest = &tail[end + 1..];
+            }
+            None => {
+                stripped.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Add the BM25 score of every document of `index` matching `terms` to `scores`
+fn score(index: &Index, terms: &[String], scores: &mut HashMap<String, f64>) {
+    let documents = index.lengths.len() as f64;
+    if documents == 0.0 {
+        return;
+    }
+    let average = index.lengths.values().sum::<usize>() as f64 / documents;
+
+    for term in terms {
+        let Some(entry) = index.entry(&Value::String(term.clone())) else {
+            continue;
+        };
+        let frequency = entry.ids.len() as f64;
+        let idf = ((documents - frequency + 0.5) / (frequency + 0.5) + 1.0).ln();
+
+        for (id, &count) in entry.ids.iter().zip(&entry.counts) {
+            let count = count as f64;
+            let length = index
+                .lengths
+                .get(id)
+                .map_or(average, |&length| length as f64);
+            let norm = K1 * (1.0 - B + B * length / average);
+            *scores.entry(id.clone()).or_default() += idf * count * (K1 + 1.0) / (count + norm);
+        }
+    }
+}
+
+impl GitBase {
+    /// 在集合的全文索引中搜索 `query` 中的词，返回至少包含一个词的文档，
+    /// 按 BM25 相关度从高到低排序；集合没有全文索引时返回 `IndexError::NotFound`
+    pub async fn search(&self, collection: &str, query: &str) -> AppResult<Vec<SearchHit>> {
+        self.get_collection(collection).await?;
+
+        let mut indexes = Vec::new();
+        for name in self.list_indexes(collection).await? {
+            let index = self.get_index(collection, &name).await?;
+            if index.kind == IndexKind::Text {
+                indexes.push(index);
+            }
+        }
+        if indexes.is_empty() {
+            return Err(Report::new(GBError::Index(IndexError::NotFound(format!(
+                "text index on {}",
+                collection
+            )))));
+        }
+
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores = HashMap::new();
+        for index in &indexes {
+            score(index, &terms, &mut scores);
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .map(|(id, score)| SearchHit { id, score })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexSpec;
+    use crate::query::Filter;
+    use crate::storage::InMemoryStorage;
+    use serde_json::json;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("# Hello, World! See [the docs](https://example.com/x) <b>now</b>"),
+            vec!["hello", "world", "see", "the", "docs", "now"]
+        );
+        assert_eq!(
+            tokenize("Rust 数据库 v2"),
+            vec!["rust", "数", "据", "库", "v2"]
+        );
+        assert_eq!(tokenize("a < b"), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+
+        let err = gitbase.search("notes", "rust").await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Index(IndexError::NotFound(_))
+        ));
+
+        let rust = gitbase
+            .insert_document(
+                "notes",
+                json!({"title": "Rust", "body": "Rust ownership and **borrowing** in Rust"}),
+            )
+            .await
+            .unwrap();
+        let git = gitbase
+            .insert_document(
+                "notes",
+                json!({"title": "Git", "body": "Storing data in Git, written in Rust"}),
+            )
+            .await
+            .unwrap();
+        gitbase
+            .insert_document("notes", json!({"title": "Cooking", "body": "Pasta"}))
+            .await
+            .unwrap();
+
+        let spec = IndexSpec::text("by_text", &["title", "body"]);
+        let index = gitbase.create_index("notes", spec).await.unwrap();
+        assert_eq!(index.lengths.len(), 3);
+        assert_eq!(index.entry(&json!("rust")).unwrap().counts.len(), 2);
+
+        let hits = gitbase.search("notes", "RUST").await.unwrap();
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, vec![rust.id.as_str(), git.id.as_str()]);
+        assert!(hits[0].score > hits[1].score);
+
+        let hits = gitbase.search("notes", "git pasta").await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(gitbase.search("notes", "python").await.unwrap().is_empty());
+
+        // The index follows document writes and is never used by queries
+        gitbase
+            .update_document("notes", &git.id, json!({"body": "Pasta"}))
+            .await
+            .unwrap();
+        gitbase.delete_document("notes", &rust.id).await.unwrap();
+        assert!(gitbase.search("notes", "rust").await.unwrap().is_empty());
+        assert_eq!(gitbase.search("notes", "pasta").await.unwrap().len(), 2);
+        gitbase
+            .create_index("notes", IndexSpec::text("by_title", &["title"]))
+            .await
+            .unwrap();
+        let found = gitbase
+            .query_documents("notes", &Filter::eq("title", json!("Git")))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+
+        let spec = IndexSpec::text("unique_text", &["title"]).unique();
+        let err = gitbase.create_index("notes", spec).await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Index(IndexError::InvalidIndex(_))
+        ));
+    }
+}
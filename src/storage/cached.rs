@@ -0,0 +1,800 @@
+use crate::cache::{CacheStats, CachedFile, DiskCache};
+use crate::error::{CacheError, CacheResult, StorageResult};
+
+use super::{git_blob_sha, Change, FileMeta, ListOptions, ListPage, Revalidation, StorageBackend};
+use async_trait::async_trait;
+use error_stack::Report;
+use lru::LruCache;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+impl<B: fmt::Display + ?Sized> fmt::Display for CachedStorage<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CachedStorage({})", self.inner)
+    }
+}
+
+/// How long the cache goes without asking the backend whether its branch head moved
+pub(crate) const HEAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a write does to the cached content of its path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Cache the written content, so reading it back needs no request
+    #[default]
+    WriteThrough,
+    /// Drop the cached content and leave caching to the next read
+    WriteAround,
+}
+
+struct CachedContent {
+    file: CachedFile,
+    stored_at: Instant,
+}
+
+struct State {
+    entries: LruCache<String, CachedContent>,
+    /// Bumped whenever an entry is dropped or replaced by a write, so that a read which fetched
+    /// its content before that does not store it afterwards
+    generation: u64,
+    /// Second level that outlives the process, with the ref its entries are kept under
+    disk: Option<(DiskCache, String)>,
+    /// Branch head the entries are known to be current at, if the backend tracks commits
+    head: Option<String>,
+    /// When the head was last checked, see [`CachedStorage::with_head_check_interval`]
+    head_checked_at: Option<Instant>,
+    stats: CacheStats,
+}
+
+impl State {
+    fn put(&mut self, path: &str, file: CachedFile) {
+        self.entries.put(
+            path.to_string(),
+            CachedContent {
+                file,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+
+    fn record(&mut self, hit: bool) {
+        match hit {
+            true => self.stats.hits += 1,
+            false => self.stats.misses += 1,
+        }
+    }
+}
+
+/// Storage backend that keeps the content of recently read files of another backend in memory,
+/// and optionally on disk.
+///
+/// Writes, deletes and commits made through the decorator keep the cache consistent. Writes by
+/// other processes are caught in three ways: entries read with an ETag are revalidated with a
+/// conditional read on every hit, the cache drops the paths changed whenever the backend's
+/// branch head moves, and a TTL bounds how long any entry is served. Cloning the storage shares
+/// the cache.
+///
+/// A cache that became unusable because a thread panicked while holding it is emptied and
+/// reused, and disk cache failures count as misses, so caching never turns the result of the
+/// wrapped backend into an error.
+pub struct CachedStorage<B: ?Sized> {
+    inner: Arc<B>,
+    state: Arc<Mutex<State>>,
+    ttl: Option<Duration>,
+    policy: WritePolicy,
+    head_check_interval: Duration,
+}
+
+impl<B: ?Sized> Clone for CachedStorage<B> {
+    fn clone(&self) -> Self {
+        CachedStorage {
+            inner: Arc::clone(&self.inner),
+            state: Arc::clone(&self.state),
+            ttl: self.ttl,
+            policy: self.policy,
+            head_check_interval: self.head_check_interval,
+        }
+    }
+}
+
+impl<B: StorageBackend> CachedStorage<B> {
+    /// Cache up to `capacity` files of `inner`, without expiry and writing through. Fails with
+    /// [`CacheError::InvalidConfig`] if `capacity` is zero.
+    pub fn new(inner: B, capacity: usize) -> CacheResult<Self> {
+        let capacity = NonZeroUsize::new(capacity).ok_or_else(|| {
+            Report::new(CacheError::InvalidConfig(
+                "Capacity must be at least 1".into(),
+            ))
+        })?;
+
+        Ok(Self::with_capacity(inner, capacity))
+    }
+
+    pub fn with_capacity(inner: B, capacity: NonZeroUsize) -> Self {
+        CachedStorage {
+            inner: Arc::new(inner),
+            state: Arc::new(Mutex::new(State {
+                entries: LruCache::new(capacity),
+                generation: 0,
+                disk: None,
+                head: None,
+                head_checked_at: None,
+                stats: CacheStats::default(),
+            })),
+            ttl: None,
+            policy: WritePolicy::default(),
+            head_check_interval: HEAD_CHECK_INTERVAL,
+        }
+    }
+
+    /// The same cache over the backend as a trait object
+    pub fn into_dyn(self) -> CachedStorage<dyn StorageBackend>
+    where
+        B: 'static,
+    {
+        CachedStorage {
+            inner: self.inner,
+            state: self.state,
+            ttl: self.ttl,
+            policy: self.policy,
+            head_check_interval: self.head_check_interval,
+        }
+    }
+}
+
+impl<B: StorageBackend + ?Sized> CachedStorage<B> {
+    /// Serve cached content for at most `ttl` after it was stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: WritePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Ask the backend for its branch head at most once per `interval` instead of every five
+    /// seconds, as the lookup is a request of its own on remote backends
+    pub fn with_head_check_interval(mut self, interval: Duration) -> Self {
+        self.head_check_interval = interval;
+        self
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Also keep read files below `dir`, so they survive restarts; several processes may share
+    /// the directory. Entries are kept apart by `reference`, the branch the backend reads from.
+    pub async fn enable_disk_cache(
+        &self,
+        dir: impl Into<PathBuf>,
+        reference: &str,
+    ) -> CacheResult<()> {
+        let disk = DiskCache::open(dir).await?;
+        self.lock().disk = Some((disk, reference.to_string()));
+
+        Ok(())
+    }
+
+    /// Drop the cached content of `path`, e.g. after it was changed by another writer
+    pub async fn invalidate(&self, path: &str) {
+        self.forget(path);
+        self.forget_on_disk([path]).await;
+    }
+
+    /// Drop every file cached in memory. Entries on disk are checked against the backend before
+    /// they are served and stay.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.entries.clear();
+        state.generation += 1;
+    }
+
+    /// Whether `path` is cached in memory, without counting a read
+    pub(crate) fn contains(&self, path: &str) -> bool {
+        self.lock().entries.contains(path)
+    }
+
+    /// Lock the cache, emptying it first if a panic left it in an unknown state
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| {
+            let mut state = poisoned.into_inner();
+            state.entries.clear();
+            state.generation += 1;
+            self.state.clear_poison();
+            state
+        })
+    }
+
+    /// The cached file at `path` unless it expired, with the generation to [`Self::store`] the
+    /// file read instead under
+    fn lookup(&self, path: &str) -> (Option<CachedFile>, u64) {
+        let mut state = self.lock();
+        let fresh = match state.entries.get(path) {
+            Some(cached) if self.ttl.is_none_or(|ttl| cached.stored_at.elapsed() < ttl) => {
+                Some(cached.file.clone())
+            }
+            Some(_) => {
+                state.entries.pop(path);
+                None
+            }
+            None => None,
+        };
+
+        (fresh, state.generation)
+    }
+
+    /// Cache `file` as read from `path`, unless a write or invalidation since `generation` may
+    /// have made it outdated
+    async fn store(&self, path: &str, file: &CachedFile, generation: u64, hit: bool) {
+        let disk = {
+            let mut state = self.lock();
+            state.record(hit);
+            if state.generation != generation {
+                return;
+            }
+            state.put(path, file.clone());
+            state.disk.clone()
+        };
+        // A write that landed meanwhile may already have dropped the path from disk, so the
+        // entry is taken back out. One racing the check is caught when the entry is promoted.
+        if let Some((disk, reference)) = disk {
+            let _ = disk.insert(&reference, path, file).await;
+            if self.lock().generation != generation {
+                let _ = disk.invalidate(&reference, path).await;
+            }
+        }
+    }
+
+    /// The disk cache entry of `path`. Another process may have written the file since, so
+    /// entries without an ETag are kept only if the backend still has their SHA.
+    async fn read_disk(&self, path: &str) -> StorageResult<Option<CachedFile>> {
+        // The lock is not held across I/O, which would serialize every read behind it
+        let Some((disk, reference)) = self.lock().disk.clone() else {
+            return Ok(None);
+        };
+        let Ok(Some(file)) = disk.get(&reference, path).await else {
+            return Ok(None);
+        };
+        if file.etag.is_none() && self.inner.sha(path).await?.as_ref() != Some(&file.sha) {
+            return Ok(None);
+        }
+
+        Ok(Some(file))
+    }
+
+    fn forget(&self, path: &str) {
+        let mut state = self.lock();
+        state.entries.pop(path);
+        state.generation += 1;
+    }
+
+    /// Drop the disk entries of `paths`. Failures are ignored: entries are checked against the
+    /// backend before they are served.
+    async fn forget_on_disk<'p>(&self, paths: impl IntoIterator<Item = &'p str>) {
+        let Some((disk, reference)) = self.lock().disk.clone() else {
+            return;
+        };
+        for path in paths {
+            let _ = disk.invalidate(&reference, path).await;
+        }
+    }
+
+    /// Apply the write policy to `path` after `content` was written to it
+    fn written(&self, path: &str, content: &str) {
+        let mut state = self.lock();
+        state.generation += 1;
+        match self.policy {
+            WritePolicy::WriteThrough => state.put(
+                path,
+                CachedFile {
+                    sha: git_blob_sha(content.as_bytes()),
+                    content: content.to_string(),
+                    etag: None,
+                },
+            ),
+            WritePolicy::WriteAround => {
+                state.entries.pop(path);
+            }
+        }
+    }
+
+    /// Bring the cache up to date with the branch head, dropping only the paths changed since
+    /// the head it was last synced with, or everything if the backend cannot tell which. Runs
+    /// at most once per head check interval.
+    async fn sync_head(&self) -> StorageResult<()> {
+        let previous = {
+            let mut state = self.lock();
+            let interval = self.head_check_interval;
+            if state
+                .head_checked_at
+                .is_some_and(|checked_at| checked_at.elapsed() < interval)
+            {
+                return Ok(());
+            }
+            state.head_checked_at = Some(Instant::now());
+            state.head.clone()
+        };
+
+        let Some(head) = self.inner.head().await? else {
+            return Ok(());
+        };
+        let changed = match previous {
+            Some(previous) if previous == head => return Ok(()),
+            Some(previous) => self.inner.changed_paths(&previous, &head).await?,
+            None => None,
+        };
+
+        // Contents are read after the head, so they are never older than the head recorded
+        {
+            let mut state = self.lock();
+            match &changed {
+                Some(paths) => {
+                    for path in paths {
+                        state.entries.pop(path);
+                    }
+                }
+                None => state.entries.clear(),
+            }
+            state.generation += 1;
+            state.head = Some(head);
+        }
+        if let Some(paths) = &changed {
+            self.forget_on_disk(paths.iter().map(String::as_str)).await;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<B: StorageBackend + ?Sized> StorageBackend for CachedStorage<B> {
+    async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+        let meta = self.inner.write(path, content).await?;
+        self.written(path, content);
+        self.forget_on_disk([path]).await;
+
+        Ok(meta)
+    }
+
+    async fn write_if(
+        &self,
+        path: &str,
+        content: &str,
+        expected_sha: Option<&str>,
+    ) -> StorageResult<FileMeta> {
+        match self.inner.write_if(path, content, expected_sha).await {
+            Ok(meta) => {
+                self.written(path, content);
+                self.forget_on_disk([path]).await;
+                Ok(meta)
+            }
+            // A conflict means the cached content may be outdated too
+            Err(e) => {
+                self.invalidate(path).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Served from memory, then from disk, then from the backend. Entries with an ETag are
+    /// revalidated with the backend, a `NotModified` answer counting as a hit.
+    async fn read(&self, path: &str) -> StorageResult<String> {
+        self.sync_head().await?;
+        let (memory, generation) = self.lookup(path);
+        let promoted = memory.is_none();
+        let cached = match memory {
+            Some(file) => Some(file),
+            None => self.read_disk(path).await?,
+        };
+
+        if let Some(file) = cached.as_ref().filter(|file| file.etag.is_none()) {
+            // Entries already in memory keep the time they were stored, for the TTL
+            match promoted {
+                true => self.store(path, file, generation, true).await,
+                false => self.lock().record(true),
+            }
+            return Ok(file.content.clone());
+        }
+
+        let etag = cached.as_ref().and_then(|file| file.etag.as_deref());
+        let revalidation = match self.inner.read_if_modified(path, etag).await {
+            Ok(revalidation) => revalidation,
+            Err(e) => {
+                self.lock().record(false);
+                return Err(e);
+            }
+        };
+        let (content, etag) = match revalidation {
+            Revalidation::NotModified => match cached {
+                Some(file) => {
+                    self.store(path, &file, generation, true).await;
+                    return Ok(file.content);
+                }
+                // Only an ETag can be answered with `NotModified`
+                None => (self.inner.read(path).await?, None),
+            },
+            Revalidation::Modified { content, etag } => (content, etag),
+        };
+        let file = CachedFile {
+            sha: git_blob_sha(content.as_bytes()),
+            content,
+            etag,
+        };
+        self.store(path, &file, generation, false).await;
+
+        Ok(file.content)
+    }
+
+    async fn read_if_modified(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> StorageResult<Revalidation> {
+        let generation = self.lock().generation;
+        let revalidation = self.inner.read_if_modified(path, etag).await?;
+        if let Revalidation::Modified { content, etag } = &revalidation {
+            let file = CachedFile {
+                sha: git_blob_sha(content.as_bytes()),
+                content: content.clone(),
+                etag: etag.clone(),
+            };
+            self.store(path, &file, generation, false).await;
+        }
+
+        Ok(revalidation)
+    }
+
+    async fn delete(&self, path: &str) -> StorageResult<()> {
+        let deleted = self.inner.delete(path).await;
+        self.invalidate(path).await;
+
+        deleted
+    }
+
+    async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+        self.inner.stat(path).await
+    }
+
+    async fn sha(&self, path: &str) -> StorageResult<Option<String>> {
+        self.inner.sha(path).await
+    }
+
+    async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+        self.inner.list(prefix, options).await
+    }
+
+    async fn exists(&self, path: &str) -> StorageResult<bool> {
+        self.inner.exists(path).await
+    }
+
+    async fn head(&self) -> StorageResult<Option<String>> {
+        self.inner.head().await
+    }
+
+    async fn changed_paths(&self, from: &str, to: &str) -> StorageResult<Option<Vec<String>>> {
+        self.inner.changed_paths(from, to).await
+    }
+
+    async fn commit(&self, message: &str, changes: &[Change]) -> StorageResult<Option<String>> {
+        let committed = self.inner.commit(message, changes).await;
+        for change in changes {
+            match (&committed, change) {
+                (Ok(_), Change::Write { path, content, .. }) => self.written(path, content),
+                _ => self.forget(change.path()),
+            }
+        }
+        self.forget_on_disk(changes.iter().map(Change::path)).await;
+
+        committed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::StorageError;
+    use crate::storage::{FileMeta, InMemoryStorage, LocalGitStorage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+
+    /// In-memory storage answering conditional reads with the blob SHA as ETag, and counting
+    /// lookups of a head that never moves
+    struct EtagStorage(InMemoryStorage, Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl StorageBackend for EtagStorage {
+        async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+            self.0.write(path, content).await
+        }
+
+        async fn write_if(
+            &self,
+            path: &str,
+            content: &str,
+            expected_sha: Option<&str>,
+        ) -> StorageResult<FileMeta> {
+            self.0.write_if(path, content, expected_sha).await
+        }
+
+        async fn read(&self, path: &str) -> StorageResult<String> {
+            self.0.read(path).await
+        }
+
+        async fn read_if_modified(
+            &self,
+            path: &str,
+            etag: Option<&str>,
+        ) -> StorageResult<Revalidation> {
+            let sha = self.0.stat(path).await?.sha;
+            if etag == Some(sha.as_str()) {
+                return Ok(Revalidation::NotModified);
+            }
+            Ok(Revalidation::Modified {
+                content: self.0.read(path).await?,
+                etag: Some(sha),
+            })
+        }
+
+        async fn delete(&self, path: &str) -> StorageResult<()> {
+            self.0.delete(path).await
+        }
+
+        async fn head(&self) -> StorageResult<Option<String>> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            Ok(Some("head".to_string()))
+        }
+
+        async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+            self.0.stat(path).await
+        }
+
+        async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+            self.0.list(prefix, options).await
+        }
+    }
+
+    /// In-memory storage whose reads fetch the content, then wait to be released before
+    /// returning it
+    #[derive(Clone)]
+    struct PausedReads {
+        files: InMemoryStorage,
+        fetched: Arc<Notify>,
+        release: Arc<Notify>,
+    }
+
+    #[async_trait]
+    impl StorageBackend for PausedReads {
+        async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+            self.files.write(path, content).await
+        }
+
+        async fn write_if(
+            &self,
+            path: &str,
+            content: &str,
+            expected_sha: Option<&str>,
+        ) -> StorageResult<FileMeta> {
+            self.files.write_if(path, content, expected_sha).await
+        }
+
+        async fn read(&self, path: &str) -> StorageResult<String> {
+            let content = self.files.read(path).await?;
+            self.fetched.notify_one();
+            self.release.notified().await;
+            Ok(content)
+        }
+
+        async fn delete(&self, path: &str) -> StorageResult<()> {
+            self.files.delete(path).await
+        }
+
+        async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+            self.files.stat(path).await
+        }
+
+        async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+            self.files.list(prefix, options).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_reads() {
+        let storage = InMemoryStorage::new();
+        let cached = CachedStorage::new(storage.clone(), 2)
+            .unwrap()
+            .with_ttl(Duration::from_millis(50));
+        storage.write("a.txt", "a").await.unwrap();
+
+        assert_eq!(cached.read("a.txt").await.unwrap(), "a");
+        storage.write("a.txt", "changed").await.unwrap();
+        assert_eq!(cached.read("a.txt").await.unwrap(), "a");
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 1 });
+
+        // Expired entries are read again
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(cached.read("a.txt").await.unwrap(), "changed");
+
+        cached.delete("a.txt").await.unwrap();
+        let err = cached.read("a.txt").await.unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+
+        let err = CachedStorage::new(storage, 0).err().unwrap();
+        assert!(matches!(
+            err.current_context(),
+            CacheError::InvalidConfig(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_write_policies() {
+        let storage = InMemoryStorage::new();
+        let through = CachedStorage::new(storage.clone(), 10).unwrap();
+        through.write("a.txt", "a").await.unwrap();
+        through
+            .commit("Batch", &[Change::write("b.txt", "b")])
+            .await
+            .unwrap();
+        assert_eq!(through.read("a.txt").await.unwrap(), "a");
+        assert_eq!(through.read("b.txt").await.unwrap(), "b");
+        assert_eq!(through.stats(), CacheStats { hits: 2, misses: 0 });
+
+        let around = CachedStorage::new(storage.clone(), 10)
+            .unwrap()
+            .with_policy(WritePolicy::WriteAround);
+        assert_eq!(around.read("a.txt").await.unwrap(), "a");
+        around.write("a.txt", "new").await.unwrap();
+        assert_eq!(around.read("a.txt").await.unwrap(), "new");
+        assert_eq!(around.stats(), CacheStats { hits: 0, misses: 2 });
+
+        // A failed precondition drops the entry
+        assert_eq!(through.read("a.txt").await.unwrap(), "a");
+        let err = through.write_if("a.txt", "stale", None).await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { .. }
+        ));
+        assert_eq!(through.read("a.txt").await.unwrap(), "new");
+    }
+
+    #[tokio::test]
+    async fn test_etag_revalidation() {
+        let storage = InMemoryStorage::new();
+        let head_checks = Arc::new(AtomicUsize::new(0));
+        let cached =
+            CachedStorage::new(EtagStorage(storage.clone(), head_checks.clone()), 10).unwrap();
+        storage.write("a.json", "1").await.unwrap();
+
+        cached.read("a.json").await.unwrap();
+        cached.read("a.json").await.unwrap();
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 1 });
+
+        // A write behind the cache's back changes the ETag and is picked up
+        storage.write("a.json", "2").await.unwrap();
+        assert_eq!(cached.read("a.json").await.unwrap(), "2");
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 2 });
+
+        // The head is looked up once per interval, not once per read
+        assert_eq!(head_checks.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_head_tracking() {
+        let dir = tempfile::tempdir().unwrap();
+        git2::Repository::init_bare(dir.path()).unwrap();
+        let open = || LocalGitStorage::open(dir.path(), None).unwrap();
+        let cached = CachedStorage::new(open(), 10)
+            .unwrap()
+            .with_head_check_interval(Duration::ZERO);
+        let other = open();
+        other.write("a.json", "1").await.unwrap();
+        other.write("b.json", "1").await.unwrap();
+        cached.read("a.json").await.unwrap();
+        cached.read("b.json").await.unwrap();
+
+        // Another writer moves the head, which drops only the paths it changed
+        other.write("a.json", "2").await.unwrap();
+        assert_eq!(cached.read("a.json").await.unwrap(), "2");
+        assert!(cached.contains("b.json"));
+        cached.read("b.json").await.unwrap();
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 3 });
+    }
+
+    #[tokio::test]
+    async fn test_read_racing_write() {
+        let files = InMemoryStorage::new();
+        let fetched = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+
+        for policy in [WritePolicy::WriteThrough, WritePolicy::WriteAround] {
+            files.write("a.txt", "old").await.unwrap();
+            let cached = CachedStorage::new(
+                PausedReads {
+                    files: files.clone(),
+                    fetched: fetched.clone(),
+                    release: release.clone(),
+                },
+                10,
+            )
+            .unwrap()
+            .with_policy(policy);
+
+            // The read fetches the old content, the write lands, then the read completes
+            let reader = cached.clone();
+            let read = tokio::spawn(async move { reader.read("a.txt").await.unwrap() });
+            fetched.notified().await;
+            cached.write("a.txt", "new").await.unwrap();
+            release.notify_one();
+            assert_eq!(read.await.unwrap(), "old");
+
+            // Reading it back must not serve the old content
+            if policy == WritePolicy::WriteAround {
+                release.notify_one();
+            }
+            assert_eq!(cached.read("a.txt").await.unwrap(), "new");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_read_racing_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = InMemoryStorage::new();
+        files.write("a.txt", "old").await.unwrap();
+        let fetched = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let storage = PausedReads {
+            files: files.clone(),
+            fetched: fetched.clone(),
+            release: release.clone(),
+        };
+        let cached = CachedStorage::new(storage.clone(), 10).unwrap();
+        cached.enable_disk_cache(dir.path(), "main").await.unwrap();
+
+        let reader = cached.clone();
+        let read = tokio::spawn(async move { reader.read("a.txt").await.unwrap() });
+        fetched.notified().await;
+        cached.write("a.txt", "new").await.unwrap();
+        release.notify_one();
+        assert_eq!(read.await.unwrap(), "old");
+
+        // Neither the memory nor the disk level serves the content the read fetched
+        assert_eq!(cached.read("a.txt").await.unwrap(), "new");
+        let disk = DiskCache::open(dir.path()).await.unwrap();
+        assert_eq!(disk.get("main", "a.txt").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_cache_is_reset() {
+        let storage = InMemoryStorage::new();
+        let cached = CachedStorage::new(storage.clone(), 10).unwrap();
+        assert!(cached.read("a.txt").await.is_err());
+        cached.write("a.txt", "a").await.unwrap();
+
+        let poisoner = cached.clone();
+        std::thread::spawn(move || {
+            let _state = poisoner.state.lock().unwrap();
+            panic!("poison the cache");
+        })
+        .join()
+        .unwrap_err();
+
+        // The write lands and the cache starts over instead of failing it
+        cached
+            .commit("Batch", &[Change::write("b.txt", "b")])
+            .await
+            .unwrap();
+        assert_eq!(storage.read("b.txt").await.unwrap(), "b");
+        assert_eq!(cached.read("a.txt").await.unwrap(), "a");
+        assert_eq!(cached.read("b.txt").await.unwrap(), "b");
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 2 });
+    }
+}
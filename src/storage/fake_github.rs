@@ -0,0 +1,160 @@
+use super::GitHubStorage;
+use octocrab::Octocrab;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A request as received: method, path with query, and body
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+type Handler = dyn Fn(&Request) -> (u16, String) + Send + Sync;
+
+/// Stand-in for the GitHub REST API so [`GitHubStorage`] can be tested offline: an HTTP server
+/// answering every request with the status and JSON body `handler` returns for it
+pub(crate) struct FakeGitHub {
+    base: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl FakeGitHub {
+    pub async fn start(
+        handler: impl Fn(&Request) -> (u16, String) + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let recorded = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorded = Arc::clone(&recorded);
+                let handler = Arc::clone(&handler);
+                tokio::spawn(async move { serve(stream, &*handler, &recorded).await });
+            }
+        });
+
+        FakeGitHub { base, requests }
+    }
+
+    pub fn storage(&self, owner: &str, repo: &str) -> GitHubStorage {
+        let client = Octocrab::builder()
+            .base_uri(self.base.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        GitHubStorage::from_client(client, owner, repo, None)
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Answer the requests of one connection until the client closes it
+async fn serve(mut stream: TcpStream, handler: &Handler, recorded: &Mutex<Vec<Request>>) {
+    let mut buffer = Vec::new();
+    loop {
+        let Some(end) = find(&buffer, b"\r\n\r\n") else {
+            let mut chunk = [0; 4096];
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            }
+            continue;
+        };
+
+        let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+        let length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        while buffer.len() < end + 4 + length {
+            let mut chunk = [0; 4096];
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            }
+        }
+
+        let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+        let request = Request {
+            method: request_line.next().unwrap_or_default().to_string(),
+            path: request_line.next().unwrap_or_default().to_string(),
+            body: String::from_utf8_lossy(&buffer[end + 4..end + 4 + length]).to_string(),
+        };
+        buffer.drain(..end + 4 + length);
+
+        let (status, body) = handler(&request);
+        recorded.lock().unwrap().push(request);
+        let response = format!(
+            "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// A `404 Not Found` as GitHub sends it
+pub(crate) fn not_found() -> (u16, String) {
+    (404, r#"{"message": "Not Found"}"#.to_string())
+}
+
+/// A repository whose default branch is `branch`
+pub(crate) fn repository(owner: &str, repo: &str, branch: &str) -> (u16, String) {
+    let body = serde_json::json!({
+        "id": 1,
+        "name": repo,
+        "url": format!("https://api.github.com/repos/{}/{}", owner, repo),
+        "default_branch": branch,
+    });
+    (200, body.to_string())
+}
+
+/// A file as the Contents API returns it
+pub(crate) fn file(path: &str, content: &str) -> (u16, String) {
+    (200, content_item(path, content).to_string())
+}
+
+/// A directory of `(path, content)` files as the Contents API lists it
+pub(crate) fn directory(files: &[(&str, &str)]) -> (u16, String) {
+    let items: Vec<_> = files
+        .iter()
+        .map(|(path, content)| content_item(path, content))
+        .collect();
+    (200, serde_json::Value::Array(items).to_string())
+}
+
+fn content_item(path: &str, content: &str) -> serde_json::Value {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let url = format!("https://api.github.com/contents/{}", path);
+    serde_json::json!({
+        "name": path.rsplit('/').next(),
+        "path": path,
+        "sha": super::git_blob_sha(content.as_bytes()),
+        "encoding": "base64",
+        "content": general_purpose::STANDARD.encode(content),
+        "size": content.len(),
+        "url": url,
+        "type": "file",
+        "_links": {"self": url},
+    })
+}
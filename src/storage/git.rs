@@ -0,0 +1,880 @@
+use crate::error::{StorageError, StorageResult};
+
+use super::{
+    check_sha, git_blob_sha, paginate, validate_path, Change, Entry, FileMeta, ListOptions,
+    ListPage, StorageBackend, DEFAULT_BRANCH,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use git2::{Commit, ErrorCode, FileMode, ObjectType, Oid, Repository};
+use git2::{Signature, Tree, TreeWalkMode, TreeWalkResult};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_AUTHOR_NAME: &str = "gitbase";
+const DEFAULT_AUTHOR_EMAIL: &str = "gitbase@localhost";
+
+impl fmt::Display for LocalGitStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LocalGitStorage(path={}, branch={})",
+            self.path.display(),
+            self.branch
+        )
+    }
+}
+
+/// Storage backend that commits every write and delete to a branch of a local Git repository.
+///
+/// Works on both bare and working-tree repositories. When the configured branch is checked out
+/// in a working tree, touched paths are also updated on disk unless they have local edits.
+pub struct LocalGitStorage {
+    repo: Arc<Mutex<Repository>>,
+    path: PathBuf,
+    branch: String,
+    identity: Option<(String, String)>,
+}
+
+impl LocalGitStorage {
+    pub fn open(path: impl AsRef<Path>, branch: Option<&str>) -> StorageResult<Self> {
+        let path = path.as_ref();
+        let repo = Repository::open(path).map_err(|e| {
+            Report::new(StorageError::Git(e.message().to_string()))
+                .attach_printable(format!("Failed to open repository: {}", path.display()))
+        })?;
+
+        Ok(LocalGitStorage {
+            repo: Arc::new(Mutex::new(repo)),
+            path: path.to_path_buf(),
+            branch: branch.unwrap_or(DEFAULT_BRANCH).to_string(),
+            identity: None,
+        })
+    }
+
+    /// Use this name and email as author and committer instead of the repository configuration
+    pub fn with_signature(mut self, name: &str, email: &str) -> Self {
+        self.identity = Some((name.to_string(), email.to_string()));
+        self
+    }
+
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// Run a blocking libgit2 operation on the repository
+    async fn with_repo<T, F>(&self, f: F) -> StorageResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Repository) -> StorageResult<T> + Send + 'static,
+    {
+        let repo = Arc::clone(&self.repo);
+
+        tokio::task::spawn_blocking(move || {
+            let repo = repo
+                .lock()
+                .map_err(|_| Report::new(StorageError::Git("Repository lock poisoned".into())))?;
+            f(&repo)
+        })
+        .await
+        .map_err(|e| {
+            Report::new(StorageError::IoError("Git task failed".into()))
+                .attach_printable(format!("Failed to join blocking task: {}", e))
+        })?
+    }
+
+    /// Branch and identity, owned so they can move into a blocking task
+    fn context(&self) -> (String, Option<(String, String)>) {
+        (self.branch.clone(), self.identity.clone())
+    }
+}
+
+fn git_error(err: git2::Error, path: &str) -> Report<StorageError> {
+    let storage_error = if err.code() == ErrorCode::NotFound {
+        StorageError::NotFound(format!("File not found: {}", path))
+    } else {
+        StorageError::Git(err.message().to_string())
+    };
+
+    Report::new(storage_error).attach_printable(format!("Git operation failed on path: {}", path))
+}
+
+fn branch_ref(branch: &str) -> String {
+    format!("refs/heads/{}", branch)
+}
+
+fn git_time(time: git2::Time) -> DateTime<Utc> {
+    DateTime::from_timestamp(time.seconds(), 0).unwrap_or_default()
+}
+
+fn signature(
+    repo: &Repository,
+    identity: &Option<(String, String)>,
+) -> Result<Signature<'static>, git2::Error> {
+    match identity {
+        Some((name, email)) => Signature::now(name, email),
+        None => repo
+            .signature()
+            .or_else(|_| Signature::now(DEFAULT_AUTHOR_NAME, DEFAULT_AUTHOR_EMAIL)),
+    }
+}
+
+/// The tip of `branch`, or `None` when the branch has no commits yet
+fn head_commit<'r>(repo: &'r Repository, branch: &str) -> StorageResult<Option<Commit<'r>>> {
+    match repo.find_reference(&branch_ref(branch)) {
+        Ok(reference) => reference
+            .peel_to_commit()
+            .map(Some)
+            .map_err(|e| git_error(e, branch)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(git_error(e, branch)),
+    }
+}
+
+fn entry_id(tree: &Tree, path: &str) -> Option<Oid> {
+    tree.get_path(Path::new(path))
+        .ok()
+        .filter(|entry| entry.kind() == Some(ObjectType::Blob))
+        .map(|entry| entry.id())
+}
+
+/// Write a copy of `base` with the blob at `components` replaced (or removed when `blob` is
+/// `None`). Returns `None` when the resulting tree is empty so empty directories are pruned.
+fn update_tree(
+    repo: &Repository,
+    base: Option<&Tree>,
+    components: &[&str],
+    blob: Option<Oid>,
+) -> Result<Option<Oid>, git2::Error> {
+    let mut builder = repo.treebuilder(base)?;
+    let Some((name, rest)) = components.split_first() else {
+        return Ok(base.map(|tree| tree.id()));
+    };
+
+    if rest.is_empty() {
+        match blob {
+            Some(oid) => {
+                builder.insert(name, oid, FileMode::Blob.into())?;
+            }
+            None => {
+                builder.remove(name)?;
+            }
+        }
+    } else {
+        let subtree = match base.and_then(|tree| tree.get_name(name)) {
+            Some(entry) if entry.kind() == Some(ObjectType::Tree) => {
+                Some(repo.find_tree(entry.id())?)
+            }
+            _ => None,
+        };
+
+        match update_tree(repo, subtree.as_ref(), rest, blob)? {
+            Some(oid) => {
+                builder.insert(name, oid, FileMode::Tree.into())?;
+            }
+            None => {
+                builder.remove(name)?;
+            }
+        }
+    }
+
+    if builder.is_empty() {
+        return Ok(None);
+    }
+    builder.write().map(Some)
+}
+
+/// Apply a single blob change to `base` and return the new root tree
+fn write_tree<'r>(
+    repo: &'r Repository,
+    base: Option<&Tree>,
+    path: &str,
+    blob: Option<Oid>,
+) -> Result<Tree<'r>, git2::Error> {
+    let components: Vec<&str> = path.split('/').collect();
+    let oid = match update_tree(repo, base, &components, blob)? {
+        Some(oid) => oid,
+        // Deleting the last file leaves an empty root tree
+        None => repo.treebuilder(None)?.write()?,
+    };
+    repo.find_tree(oid)
+}
+
+/// The latest commit at or before `from` on first-parent history that changed `path`, and
+/// whether that change created it
+fn last_change<'r>(from: &Commit<'r>, path: &str) -> StorageResult<(Commit<'r>, bool)> {
+    let mut commit = from.clone();
+
+    loop {
+        let tree = commit.tree().map_err(|e| git_error(e, path))?;
+        let id = entry_id(&tree, path);
+        let parent = commit.parent(0).ok();
+        let parent_id = match &parent {
+            Some(parent) => {
+                let tree = parent.tree().map_err(|e| git_error(e, path))?;
+                entry_id(&tree, path)
+            }
+            None => None,
+        };
+
+        match parent {
+            Some(parent) if id == parent_id => commit = parent,
+            _ => return Ok((commit, parent_id.is_none())),
+        }
+    }
+}
+
+/// Walk first-parent history from `head` to find when `path` was created and last modified
+fn path_times(head: &Commit, path: &str) -> StorageResult<(DateTime<Utc>, DateTime<Utc>)> {
+    let (mut commit, mut created) = last_change(head, path)?;
+    let modified = git_time(commit.time());
+
+    while !created {
+        let Ok(parent) = commit.parent(0) else {
+            break;
+        };
+        (commit, created) = last_change(&parent, path)?;
+    }
+
+    Ok((git_time(commit.time()), modified))
+}
+
+/// Mirror a committed change of `path` into the working tree when `branch` is checked out.
+///
+/// The file is only touched if it still matches the previous blob, so local edits are never
+/// overwritten; the commit has already landed either way.
+fn sync_worktree(
+    repo: &Repository,
+    branch: &str,
+    path: &str,
+    previous: Option<Oid>,
+    content: Option<&[u8]>,
+) {
+    let Some(workdir) = repo.workdir() else {
+        return;
+    };
+    let checked_out = repo
+        .head()
+        .ok()
+        .and_then(|head| head.name().map(|name| name == branch_ref(branch)))
+        .unwrap_or(false);
+    if !checked_out {
+        return;
+    }
+
+    let full_path = workdir.join(path);
+    let on_disk = std::fs::read(&full_path).ok().map(|c| git_blob_sha(&c));
+    if on_disk != previous.map(|oid| oid.to_string()) {
+        return;
+    }
+
+    let updated = match content {
+        Some(content) => full_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&full_path, content))
+            .is_ok(),
+        None => std::fs::remove_file(&full_path).is_ok(),
+    };
+    if !updated {
+        return;
+    }
+
+    if let Ok(mut index) = repo.index() {
+        let staged = match content {
+            Some(_) => index.add_path(Path::new(path)),
+            None => index.remove_path(Path::new(path)),
+        };
+        let _ = staged.and_then(|_| index.write());
+    }
+}
+
+/// Apply `changes` on top of the tip of `branch` and record them as a single commit.
+///
+/// Returns `None` when the changes leave the tree as it was, in which case no commit is made.
+/// libgit2 only moves the branch if its tip is still the parent we built on, so a concurrent
+/// writer makes this fail instead of being overwritten.
+fn commit_changes(
+    repo: &Repository,
+    branch: &str,
+    identity: &Option<(String, String)>,
+    message: &str,
+    changes: &[Change],
+) -> StorageResult<Option<Oid>> {
+    let head = head_commit(repo, branch)?;
+    let base = match &head {
+        Some(commit) => Some(commit.tree().map_err(|e| git_error(e, branch))?),
+        None => None,
+    };
+
+    let mut tree = base.clone();
+    for change in changes {
+        let path = change.path();
+        if let Some(expected) = change.expected_sha() {
+            let current = tree.as_ref().and_then(|t| entry_id(t, path));
+            check_sha(
+                path,
+                Some(expected),
+                current.map(|oid| oid.to_string()).as_deref(),
+            )?;
+        }
+
+        let blob = match change {
+            Change::Write { content, .. } => Some(
+                repo.blob(content.as_bytes())
+                    .map_err(|e| git_error(e, path))?,
+            ),
+            Change::Delete { .. } => {
+                if tree.as_ref().and_then(|t| entry_id(t, path)).is_none() {
+                    return Err(Report::new(StorageError::NotFound(format!(
+                        "File not found: {}",
+                        path
+                    ))));
+                }
+                None
+            }
+        };
+        tree = Some(write_tree(repo, tree.as_ref(), path, blob).map_err(|e| git_error(e, path))?);
+    }
+
+    let Some(tree) = tree else {
+        return Ok(None);
+    };
+    if base.as_ref().map(|b| b.id()) == Some(tree.id()) {
+        return Ok(None);
+    }
+
+    let sig = signature(repo, identity).map_err(|e| git_error(e, branch))?;
+    let parents: Vec<&Commit> = head.iter().collect();
+    let oid = repo
+        .commit(
+            Some(&branch_ref(branch)),
+            &sig,
+            &sig,
+            message,
+            &tree,
+            &parents,
+        )
+        .map_err(|e| git_error(e, branch))?;
+
+    let mut synced: Vec<&str> = Vec::new();
+    for path in changes.iter().map(Change::path) {
+        if synced.contains(&path) {
+            continue;
+        }
+        synced.push(path);
+
+        let previous = base.as_ref().and_then(|t| entry_id(t, path));
+        let blob = entry_id(&tree, path).and_then(|oid| repo.find_blob(oid).ok());
+        sync_worktree(
+            repo,
+            branch,
+            path,
+            previous,
+            blob.as_ref().map(|b| b.content()),
+        );
+    }
+
+    Ok(Some(oid))
+}
+
+/// Metadata for `path` after `content` has been committed to `branch`. A write that created the
+/// file stops the history walk at its own commit.
+fn written_meta(
+    repo: &Repository,
+    branch: &str,
+    path: &str,
+    content: &str,
+) -> StorageResult<FileMeta> {
+    let head = head_commit(repo, branch)?.ok_or_else(|| {
+        Report::new(StorageError::Git(format!(
+            "Branch {} has no commits",
+            branch
+        )))
+    })?;
+    let (created, modified) = path_times(&head, path)?;
+
+    Ok(FileMeta {
+        sha: git_blob_sha(content.as_bytes()),
+        created,
+        modified,
+    })
+}
+
+#[async_trait]
+impl StorageBackend for LocalGitStorage {
+    async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+        let (branch, identity) = self.context();
+        let path = path.to_string();
+        let content = content.to_string();
+
+        self.with_repo(move |repo| {
+            let existing = match head_commit(repo, &branch)? {
+                Some(commit) => {
+                    let tree = commit.tree().map_err(|e| git_error(e, &path))?;
+                    entry_id(&tree, &path)
+                }
+                None => None,
+            };
+            let message = match existing {
+                Some(_) => format!("Update {}", path),
+                None => format!("Create {}", path),
+            };
+
+            // Unchanged content produces no commit, as with `git commit` on a clean tree
+            let change = Change::write(path.as_str(), content.as_str());
+            commit_changes(repo, &branch, &identity, &message, &[change])?;
+
+            written_meta(repo, &branch, &path, &content)
+        })
+        .await
+    }
+
+    async fn write_if(
+        &self,
+        path: &str,
+        content: &str,
+        expected_sha: Option<&str>,
+    ) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+        let (branch, identity) = self.context();
+        let path = path.to_string();
+        let content = content.to_string();
+        let expected_sha = expected_sha.map(str::to_string);
+
+        self.with_repo(move |repo| {
+            let change = match &expected_sha {
+                Some(expected) => {
+                    Change::write(path.as_str(), content.as_str()).expecting(expected.as_str())
+                }
+                None => {
+                    // Create-only: the path must be absent at the tip we are about to build on
+                    let current = match head_commit(repo, &branch)? {
+                        Some(commit) => {
+                            let tree = commit.tree().map_err(|e| git_error(e, &path))?;
+                            entry_id(&tree, &path)
+                        }
+                        None => None,
+                    };
+                    let current = current.map(|oid| oid.to_string());
+                    check_sha(&path, None, current.as_deref())?;
+                    Change::write(path.as_str(), content.as_str())
+                }
+            };
+            let message = match expected_sha {
+                Some(_) => format!("Update {}", path),
+                None => format!("Create {}", path),
+            };
+
+            commit_changes(repo, &branch, &identity, &message, &[change])?;
+
+            written_meta(repo, &branch, &path, &content)
+        })
+        .await
+    }
+
+    async fn read(&self, path: &str) -> StorageResult<String> {
+        validate_path(path)?;
+        let (branch, _) = self.context();
+        let path = path.to_string();
+
+        self.with_repo(move |repo| {
+            let not_found =
+                || Report::new(StorageError::NotFound(format!("File not found: {}", path)));
+
+            let head = head_commit(repo, &branch)?.ok_or_else(not_found)?;
+            let tree = head.tree().map_err(|e| git_error(e, &path))?;
+            let oid = entry_id(&tree, &path).ok_or_else(not_found)?;
+            let blob = repo.find_blob(oid).map_err(|e| git_error(e, &path))?;
+
+            String::from_utf8(blob.content().to_vec()).map_err(|e| {
+                Report::new(StorageError::IoError("Invalid UTF-8 content".into()))
+                    .attach_printable(format!("Failed to convert bytes to UTF-8: {}", e))
+            })
+        })
+        .await
+    }
+
+    async fn delete(&self, path: &str) -> StorageResult<()> {
+        validate_path(path)?;
+        let (branch, identity) = self.context();
+        let message = format!("Delete {}", path);
+        let change = Change::delete(path);
+
+        self.with_repo(move |repo| {
+            commit_changes(repo, &branch, &identity, &message, &[change])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+        let (branch, _) = self.context();
+        let path = path.to_string();
+
+        self.with_repo(move |repo| {
+            let not_found =
+                || Report::new(StorageError::NotFound(format!("File not found: {}", path)));
+
+            let head = head_commit(repo, &branch)?.ok_or_else(not_found)?;
+            let tree = head.tree().map_err(|e| git_error(e, &path))?;
+            let oid = entry_id(&tree, &path).ok_or_else(not_found)?;
+            let (created, modified) = path_times(&head, &path)?;
+
+            Ok(FileMeta {
+                sha: oid.to_string(),
+                created,
+                modified,
+            })
+        })
+        .await
+    }
+
+    async fn sha(&self, path: &str) -> StorageResult<Option<String>> {
+        validate_path(path)?;
+        let (branch, _) = self.context();
+        let path = path.to_string();
+
+        self.with_repo(move |repo| {
+            let Some(head) = head_commit(repo, &branch)? else {
+                return Ok(None);
+            };
+            let tree = head.tree().map_err(|e| git_error(e, &path))?;
+            Ok(entry_id(&tree, &path).map(|oid| oid.to_string()))
+        })
+        .await
+    }
+
+    /// A tree lookup, where `stat` would also walk the history for the commit times
+    async fn exists(&self, path: &str) -> StorageResult<bool> {
+        Ok(self.sha(path).await?.is_some())
+    }
+
+    async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+        let (branch, _) = self.context();
+        let prefix = prefix.trim_end_matches('/').to_string();
+        let options = options.clone();
+
+        self.with_repo(move |repo| {
+            let Some(head) = head_commit(repo, &branch)? else {
+                return Ok(ListPage::default());
+            };
+            let root = head.tree().map_err(|e| git_error(e, &prefix))?;
+            let tree = if prefix.is_empty() {
+                root
+            } else {
+                match root.get_path(Path::new(&prefix)) {
+                    Ok(entry) if entry.kind() == Some(ObjectType::Tree) => repo
+                        .find_tree(entry.id())
+                        .map_err(|e| git_error(e, &prefix))?,
+                    _ => return Ok(ListPage::default()),
+                }
+            };
+            let full_path = |relative: String| match prefix.is_empty() {
+                true => relative,
+                false => format!("{}/{}", prefix, relative),
+            };
+
+            let mut entries = Vec::new();
+            if options.recursive {
+                tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+                    if entry.kind() == Some(ObjectType::Blob) {
+                        if let Some(name) = entry.name() {
+                            entries.push(Entry::file(full_path(format!("{}{}", dir, name))));
+                        }
+                    }
+                    TreeWalkResult::Ok
+                })
+                .map_err(|e| git_error(e, &prefix))?;
+            } else {
+                for entry in tree.iter() {
+                    let Some(name) = entry.name() else {
+                        continue;
+                    };
+                    match entry.kind() {
+                        Some(ObjectType::Blob) => entries.push(Entry::file(full_path(name.into()))),
+                        Some(ObjectType::Tree) => entries.push(Entry::dir(full_path(name.into()))),
+                        _ => {}
+                    }
+                }
+            }
+
+            Ok(paginate(entries, &options))
+        })
+        .await
+    }
+
+    async fn head(&self) -> StorageResult<Option<String>> {
+        let (branch, _) = self.context();
+
+        self.with_repo(move |repo| {
+            Ok(head_commit(repo, &branch)?.map(|commit| commit.id().to_string()))
+        })
+        .await
+    }
+
+    async fn changed_paths(&self, from: &str, to: &str) -> StorageResult<Option<Vec<String>>> {
+        let (from, to) = (from.to_string(), to.to_string());
+
+        self.with_repo(move |repo| {
+            let tree = |sha: &str| {
+                let oid = Oid::from_str(sha).ok()?;
+                repo.find_commit(oid).ok()?.tree().ok()
+            };
+            // A commit missing from the repository was dropped by a history rewrite
+            let (Some(old), Some(new)) = (tree(&from), tree(&to)) else {
+                return Ok(None);
+            };
+
+            let diff = repo
+                .diff_tree_to_tree(Some(&old), Some(&new), None)
+                .map_err(|e| git_error(e, &to))?;
+            let mut paths = Vec::new();
+            for delta in diff.deltas() {
+                for file in [delta.old_file(), delta.new_file()] {
+                    if let Some(path) = file.path().and_then(Path::to_str) {
+                        if !paths.iter().any(|known| known == path) {
+                            paths.push(path.to_string());
+                        }
+                    }
+                }
+            }
+
+            Ok(Some(paths))
+        })
+        .await
+    }
+
+    async fn commit(&self, message: &str, changes: &[Change]) -> StorageResult<Option<String>> {
+        for change in changes {
+            validate_path(change.path())?;
+        }
+        let (branch, identity) = self.context();
+        let message = message.to_string();
+        let changes = changes.to_vec();
+
+        self.with_repo(move |repo| {
+            let oid = commit_changes(repo, &branch, &identity, &message, &changes)?;
+            Ok(oid.map(|oid| oid.to_string()))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::RepositoryInitOptions;
+
+    fn init_repo(dir: &Path, bare: bool) -> Repository {
+        let mut opts = RepositoryInitOptions::new();
+        opts.bare(bare).initial_head("main");
+        Repository::init_opts(dir, &opts).unwrap()
+    }
+
+    fn commit_messages(repo: &Repository) -> Vec<String> {
+        let mut walk = repo.revwalk().unwrap();
+        walk.push_ref("refs/heads/main").unwrap();
+        walk.map(|oid| {
+            let commit = repo.find_commit(oid.unwrap()).unwrap();
+            commit.message().unwrap().to_string()
+        })
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn test_bare_repository_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = init_repo(dir.path(), true);
+        let storage = LocalGitStorage::open(dir.path(), None)
+            .unwrap()
+            .with_signature("Ada", "ada@example.com");
+
+        let meta = storage
+            .write("collections/notes/a.json", "hello world\n")
+            .await
+            .unwrap();
+        assert_eq!(meta.sha, "3b18e512dba79e4c8300dd08aeb37f8e728b8dad");
+
+        // Unchanged content does not create a commit
+        storage
+            .write("collections/notes/a.json", "hello world\n")
+            .await
+            .unwrap();
+        storage
+            .write("collections/notes/a.json", "bye\n")
+            .await
+            .unwrap();
+        storage.write("README.md", "# db\n").await.unwrap();
+        assert_eq!(
+            storage.read("collections/notes/a.json").await.unwrap(),
+            "bye\n"
+        );
+
+        storage.delete("collections/notes/a.json").await.unwrap();
+        let err = storage.read("collections/notes/a.json").await.unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+
+        assert_eq!(
+            commit_messages(&repo),
+            vec![
+                "Delete collections/notes/a.json",
+                "Create README.md",
+                "Update collections/notes/a.json",
+                "Create collections/notes/a.json",
+            ]
+        );
+
+        let head = repo.find_reference("refs/heads/main").unwrap();
+        let commit = head.peel_to_commit().unwrap();
+        assert_eq!(commit.author().name(), Some("Ada"));
+        assert_eq!(commit.committer().email(), Some("ada@example.com"));
+        // The emptied `collections/` directory is pruned from the tree
+        assert!(commit.tree().unwrap().get_name("collections").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_commit_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = init_repo(dir.path(), true);
+        let storage = LocalGitStorage::open(dir.path(), None).unwrap();
+        storage.write("a.json", "a").await.unwrap();
+
+        let err = storage
+            .commit(
+                "Broken batch",
+                &[Change::write("b.json", "b"), Change::delete("missing.json")],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+
+        let id = storage
+            .commit(
+                "Swap a for b",
+                &[
+                    Change::write("dir/b.json", "b"),
+                    Change::write("dir/c.json", "c"),
+                    Change::delete("a.json"),
+                ],
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let head = repo.find_reference("refs/heads/main").unwrap();
+        assert_eq!(head.target().unwrap().to_string(), id);
+        assert_eq!(
+            commit_messages(&repo),
+            vec!["Swap a for b", "Create a.json"]
+        );
+        assert_eq!(storage.read("dir/c.json").await.unwrap(), "c");
+        assert_eq!(
+            storage
+                .list("", &ListOptions::default())
+                .await
+                .unwrap()
+                .entries,
+            vec![Entry::dir("dir")]
+        );
+        assert_eq!(
+            storage.list_files("dir").await.unwrap(),
+            vec!["dir/b.json", "dir/c.json"]
+        );
+        let meta = storage.stat("dir/b.json").await.unwrap();
+        assert_eq!(meta.sha, git_blob_sha(b"b"));
+
+        // A batch that changes nothing does not commit
+        let id = storage
+            .commit("Noop", &[Change::write("dir/c.json", "c")])
+            .await
+            .unwrap();
+        assert!(id.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_write_if() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = init_repo(dir.path(), true);
+        let storage = LocalGitStorage::open(dir.path(), None).unwrap();
+
+        let meta = storage.write_if("a.json", "1", None).await.unwrap();
+        let err = storage.write_if("a.json", "2", None).await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { current: Some(sha), .. } if *sha == meta.sha
+        ));
+
+        storage
+            .write_if("a.json", "2", Some(&meta.sha))
+            .await
+            .unwrap();
+        let err = storage
+            .write_if("a.json", "3", Some(&meta.sha))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { .. }
+        ));
+        assert_eq!(
+            commit_messages(&repo),
+            vec!["Update a.json", "Create a.json"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_last_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = init_repo(dir.path(), true);
+        let storage = LocalGitStorage::open(dir.path(), None).unwrap();
+        let branch = storage.branch.clone();
+
+        storage.write("a.json", "1").await.unwrap();
+        let created = head_commit(&repo, &branch).unwrap().unwrap().id();
+        let written = storage.write("a.json", "2").await.unwrap();
+        let updated = head_commit(&repo, &branch).unwrap().unwrap().id();
+        storage.write("b.json", "1").await.unwrap();
+
+        let head = head_commit(&repo, &branch).unwrap().unwrap();
+        let (commit, was_created) = last_change(&head, "a.json").unwrap();
+        assert_eq!((commit.id(), was_created), (updated, false));
+        let (commit, was_created) = last_change(&commit.parent(0).unwrap(), "a.json").unwrap();
+        assert_eq!((commit.id(), was_created), (created, true));
+
+        let sha = storage.sha("a.json").await.unwrap();
+        assert_eq!(sha, Some(git_blob_sha(b"2")));
+        assert_eq!(storage.sha("c.json").await.unwrap(), None);
+        assert!(storage.exists("b.json").await.unwrap());
+        assert!(!storage.exists("c.json").await.unwrap());
+
+        let meta = storage.stat("a.json").await.unwrap();
+        let time = |id| git_time(repo.find_commit(id).unwrap().time());
+        assert_eq!(
+            (meta.created, meta.modified),
+            (time(created), time(updated))
+        );
+        assert_eq!(
+            (written.created, written.modified),
+            (meta.created, meta.modified)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_working_tree_is_updated() {
+        let dir = tempfile::tempdir().unwrap();
+        init_repo(dir.path(), false);
+        let storage = LocalGitStorage::open(dir.path(), Some("main")).unwrap();
+
+        storage.write("notes/a.json", "{}").await.unwrap();
+        let on_disk = std::fs::read_to_string(dir.path().join("notes/a.json")).unwrap();
+        assert_eq!(on_disk, "{}");
+
+        storage.delete("notes/a.json").await.unwrap();
+        assert!(!dir.path().join("notes/a.json").exists());
+    }
+}
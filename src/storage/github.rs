@@ -1,10 +1,18 @@
 use crate::error::{GitHubStorageError, GitHubStorageResult, StorageError, StorageResult};
 
-use super::{FileMeta, StorageBackend};
+use super::{
+    check_sha, entries_below, git_blob_sha, paginate, validate_path, Change, Entry, FileMeta,
+    ListOptions, ListPage, Revalidation, StorageBackend, DEFAULT_BRANCH,
+};
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
 use error_stack::Report;
+use octocrab::models::repos::{CommitAuthor, Content};
+use octocrab::repos::{DeleteFileBuilder, UpdateFileBuilder};
 use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 
 impl fmt::Display for GitHubStorage {
@@ -17,11 +25,13 @@ impl fmt::Display for GitHubStorage {
     }
 }
 
+#[derive(Debug)]
 pub struct GitHubStorage {
     client: Octocrab,
     owner: String,
     repo: String,
     branch: String,
+    identity: Option<CommitAuthor>,
 }
 
 impl GitHubStorage {
@@ -38,20 +48,477 @@ impl GitHubStorage {
                 Report::new(GitHubStorageError::AuthError).attach_printable(e.to_string())
             })?;
 
-        Ok(GitHubStorage {
+        Ok(Self::from_client(client, owner, repo, branch))
+    }
+
+    /// Build a storage on top of an already configured client
+    pub fn from_client(client: Octocrab, owner: &str, repo: &str, branch: Option<&str>) -> Self {
+        GitHubStorage {
             client,
             owner: owner.to_string(),
             repo: repo.to_string(),
-            branch: branch.unwrap_or("main").to_string(),
+            branch: branch.unwrap_or(DEFAULT_BRANCH).to_string(),
+            identity: None,
+        }
+    }
+
+    /// Use this name and email as author and committer instead of the token's account
+    pub fn with_signature(mut self, name: &str, email: &str) -> Self {
+        self.identity = Some(CommitAuthor {
+            name: name.to_string(),
+            email: email.to_string(),
+            date: None,
+        });
+        self
+    }
+
+    /// The same repository on another branch
+    pub fn with_branch(mut self, branch: &str) -> Self {
+        self.branch = branch.to_string();
+        self
+    }
+
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// The repository's default branch on GitHub
+    pub async fn default_branch(&self) -> StorageResult<String> {
+        let repository = self
+            .client
+            .repos(&self.owner, &self.repo)
+            .get()
+            .await
+            .map_err(|e| api_error(e, "get repository"))?;
+
+        repository.default_branch.ok_or_else(|| {
+            Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
+                "Repository has no default branch".into(),
+            )))
         })
     }
 
-    async fn create_file(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
-        let commit = self
+    /// Whether the repository exists and the token can see it
+    pub async fn repository_exists(&self) -> StorageResult<bool> {
+        match self.client.repos(&self.owner, &self.repo).get().await {
+            Ok(_) => Ok(true),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::NOT_FOUND =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(api_error(e, "get repository")),
+        }
+    }
+
+    /// Create the repository as a private one, owned by the token's user if that is `owner` and
+    /// by the organization `owner` otherwise. It starts with a README, since the Git Data API
+    /// used by [`StorageBackend::commit`] cannot write to a repository without commits.
+    pub async fn create_repository(&self) -> StorageResult<()> {
+        let user = self
+            .client
+            .current()
+            .user()
+            .await
+            .map_err(|e| api_error(e, "get current user"))?;
+        let route = if user.login == self.owner {
+            "/user/repos".to_string()
+        } else {
+            format!("/orgs/{}/repos", self.owner)
+        };
+
+        let _: octocrab::models::Repository = self
+            .client
+            .post(
+                route,
+                Some(&NewRepository {
+                    name: &self.repo,
+                    private: true,
+                    auto_init: true,
+                }),
+            )
+            .await
+            .map_err(|e| api_error(e, "create repository"))?;
+
+        Ok(())
+    }
+
+    /// Commit a Contents API file change to our branch, under our identity if one is set
+    fn signed<'o, 'r>(&self, builder: UpdateFileBuilder<'o, 'r>) -> UpdateFileBuilder<'o, 'r> {
+        let builder = builder.branch(&self.branch);
+        match &self.identity {
+            Some(identity) => builder.author(identity.clone()).commiter(identity.clone()),
+            None => builder,
+        }
+    }
+
+    fn signed_delete<'o, 'r>(
+        &self,
+        builder: DeleteFileBuilder<'o, 'r>,
+    ) -> DeleteFileBuilder<'o, 'r> {
+        let builder = builder.branch(&self.branch);
+        match &self.identity {
+            Some(identity) => builder.author(identity.clone()).commiter(identity.clone()),
+            None => builder,
+        }
+    }
+
+    fn git_route(&self, endpoint: &str) -> String {
+        format!("/repos/{}/{}/git/{}", self.owner, self.repo, endpoint)
+    }
+
+    /// The commit the branch currently points at, or `None` if the branch does not exist yet
+    async fn branch_head(&self) -> StorageResult<Option<String>> {
+        let route = self.git_route(&format!("ref/heads/{}", self.branch));
+        match self.client.get::<GitRef, _, ()>(route, None).await {
+            Ok(reference) => Ok(Some(reference.object.sha)),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::NOT_FOUND =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(api_error(e, "get branch ref")),
+        }
+    }
+
+    /// Build blobs, a tree and a commit for `changes`, then fast-forward the branch to it
+    async fn commit_changes(&self, message: &str, changes: &[Change]) -> StorageResult<String> {
+        let parent = self.branch_head().await?;
+        let base_tree = match &parent {
+            Some(sha) => {
+                let commit: GitCommit = self
+                    .client
+                    .get(self.git_route(&format!("commits/{}", sha)), None::<&()>)
+                    .await
+                    .map_err(|e| api_error(e, "get head commit"))?;
+                Some(commit.tree.sha)
+            }
+            None => None,
+        };
+
+        // Preconditions and deleted paths are checked against the parent commit, and later
+        // changes in the batch against what earlier ones staged. The non-forced ref update below
+        // keeps them valid.
+        let mut shas: HashMap<&str, Option<String>> = HashMap::new();
+        for change in changes {
+            let path = change.path();
+            let deletes = matches!(change, Change::Delete { .. });
+            if (deletes || change.expected_sha().is_some()) && !shas.contains_key(path) {
+                let current = match &parent {
+                    Some(parent) => self.current_sha(path, parent).await?,
+                    None => None,
+                };
+                shas.insert(path, current);
+            }
+            let current = shas.get(path).cloned().flatten();
+            if let Some(expected) = change.expected_sha() {
+                check_sha(path, Some(expected), current.as_deref())?;
+            }
+
+            let staged = match change {
+                Change::Write { content, .. } => Some(git_blob_sha(content.as_bytes())),
+                Change::Delete { .. } if current.is_some() => None,
+                Change::Delete { .. } => {
+                    return Err(Report::new(StorageError::NotFound(format!(
+                        "File not found: {}",
+                        path
+                    ))))
+                }
+            };
+            shas.insert(path, staged);
+        }
+
+        // The trees API takes one entry per path, so only the last change to each path counts
+        let mut last: Vec<&Change> = Vec::with_capacity(changes.len());
+        let mut positions: HashMap<&str, usize> = HashMap::new();
+        for change in changes {
+            match positions.get(change.path()) {
+                Some(&position) => last[position] = change,
+                None => {
+                    positions.insert(change.path(), last.len());
+                    last.push(change);
+                }
+            }
+        }
+
+        let mut tree = Vec::with_capacity(last.len());
+        for change in last {
+            let sha = match change {
+                Change::Write { content, .. } => {
+                    let blob: GitObject = self
+                        .client
+                        .post(
+                            self.git_route("blobs"),
+                            Some(&NewBlob {
+                                content,
+                                encoding: "utf-8",
+                            }),
+                        )
+                        .await
+                        .map_err(|e| api_error(e, "create blob"))?;
+                    Some(blob.sha)
+                }
+                // A `null` SHA removes the path from the base tree
+                Change::Delete { .. } => None,
+            };
+
+            tree.push(NewTreeEntry {
+                path: change.path(),
+                mode: "100644",
+                kind: "blob",
+                sha,
+            });
+        }
+
+        let tree: GitObject = self
+            .client
+            .post(
+                self.git_route("trees"),
+                Some(&NewTree {
+                    base_tree: base_tree.as_deref(),
+                    tree,
+                }),
+            )
+            .await
+            .map_err(|e| api_error(e, "create tree"))?;
+
+        let commit: GitObject = self
+            .client
+            .post(
+                self.git_route("commits"),
+                Some(&NewCommit {
+                    message,
+                    tree: &tree.sha,
+                    parents: parent.iter().map(String::as_str).collect(),
+                    author: self.identity.as_ref(),
+                    committer: self.identity.as_ref(),
+                }),
+            )
+            .await
+            .map_err(|e| api_error(e, "create commit"))?;
+
+        // Moving the ref is the single step that publishes the batch, and it never forces:
+        // if another writer advanced the branch meanwhile, GitHub rejects the update
+        match parent {
+            Some(parent) => {
+                let reference = format!("refs/heads/{}", self.branch);
+                let updated: Result<GitRef, _> = self
+                    .client
+                    .patch(
+                        self.git_route(&reference),
+                        Some(&UpdateRef {
+                            sha: &commit.sha,
+                            force: false,
+                        }),
+                    )
+                    .await;
+
+                if let Err(e) = updated {
+                    if is_conflict(&e) {
+                        let current = self.branch_head().await?;
+                        check_sha(&reference, Some(&parent), current.as_deref())?;
+                    }
+                    return Err(api_error(e, "update branch ref"));
+                }
+            }
+            None => {
+                let _: GitRef = self
+                    .client
+                    .post(
+                        self.git_route("refs"),
+                        Some(&NewRef {
+                            r#ref: format!("refs/heads/{}", self.branch),
+                            sha: &commit.sha,
+                        }),
+                    )
+                    .await
+                    .map_err(|e| api_error(e, "create branch ref"))?;
+            }
+        }
+
+        Ok(commit.sha)
+    }
+
+    /// Blob SHA of `path` at `reference`, or `None` if the file does not exist there
+    async fn current_sha(&self, path: &str, reference: &str) -> StorageResult<Option<String>> {
+        let result = self
+            .client
+            .repos(&self.owner, &self.repo)
+            .get_content()
+            .path(path)
+            .r#ref(reference)
+            .send()
+            .await;
+
+        match result {
+            Ok(contents) => Ok(contents.items.first().map(|item| item.sha.clone())),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::NOT_FOUND =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(api_error(e, "get content")),
+        }
+    }
+
+    /// Author date of the oldest commit touching `path` on the branch
+    async fn first_commit_date(&self, path: &str) -> StorageResult<DateTime<Utc>> {
+        let page = self
+            .client
+            .repos(&self.owner, &self.repo)
+            .list_commits()
+            .path(path)
+            .sha(&self.branch)
+            .per_page(1)
+            .send()
+            .await
+            .map_err(|e| api_error(e, "list commits"))?;
+
+        // With one commit per page, the last page holds the oldest commit
+        let oldest = match &page.last {
+            Some(_) => self
+                .client
+                .get_page(&page.last)
+                .await
+                .map_err(|e| api_error(e, "list commits"))?
+                .map(|page| page.items)
+                .unwrap_or_default(),
+            None => page.items,
+        };
+
+        oldest
+            .last()
+            .and_then(|commit| commit.commit.author.as_ref())
+            .and_then(|author| author.date)
+            .ok_or_else(|| {
+                Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
+                    "No commit history found for file".into(),
+                )))
+            })
+    }
+
+    /// Committer date of the newest commit touching `path` on the branch
+    async fn last_commit_date(&self, path: &str) -> StorageResult<DateTime<Utc>> {
+        let page = self
+            .client
+            .repos(&self.owner, &self.repo)
+            .list_commits()
+            .path(path)
+            .sha(&self.branch)
+            .per_page(1)
+            .send()
+            .await
+            .map_err(|e| api_error(e, "list commits"))?;
+
+        page.items
+            .first()
+            .and_then(|commit| commit.commit.committer.as_ref())
+            .and_then(|committer| committer.date)
+            .ok_or_else(|| {
+                Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
+                    "No commit history found for file".into(),
+                )))
+            })
+    }
+
+    /// Every entry of the branch's tree via the Trees API, or nothing if the branch is empty
+    async fn tree_entries(&self) -> StorageResult<Vec<GitTreeEntry>> {
+        let Some(head) = self.branch_head().await? else {
+            return Ok(Vec::new());
+        };
+
+        let tree: GitTree = self
+            .client
+            .get(
+                self.git_route(&format!("trees/{}", head)),
+                Some(&[("recursive", "1")]),
+            )
+            .await
+            .map_err(|e| api_error(e, "get tree"))?;
+        if tree.truncated {
+            return Err(
+                Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
+                    "Tree listing truncated".into(),
+                )))
+                .attach_printable("The repository exceeds the Trees API limits"),
+            );
+        }
+
+        Ok(tree.tree)
+    }
+
+    /// Entries below `prefix` as `list` returns them, computed from the full tree
+    async fn tree_listing(&self, prefix: &str, recursive: bool) -> StorageResult<Vec<Entry>> {
+        let tree = self.tree_entries().await?;
+        let files = tree
+            .iter()
+            .filter(|entry| entry.kind == "blob")
+            .map(|entry| entry.path.as_str());
+
+        Ok(entries_below(files, prefix, recursive))
+    }
+
+    /// Direct children of the directory `prefix` via the Contents API, which returns a listing
+    /// without file contents. `None` if the directory does not exist.
+    async fn directory(&self, prefix: &str) -> StorageResult<Option<Vec<Content>>> {
+        let result = self
             .client
             .repos(&self.owner, &self.repo)
-            .create_file(path, format!("Create {}", path), content)
-            .branch(&self.branch)
+            .get_content()
+            .path(prefix)
+            .r#ref(&self.branch)
+            .send()
+            .await;
+
+        match result {
+            // A file at `prefix` comes back as a single item carrying its own path
+            Ok(contents) if contents.items.iter().any(|item| item.path == prefix) => Ok(None),
+            Ok(contents) => Ok(Some(contents.items)),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::NOT_FOUND =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(api_error(e, "list directory")),
+        }
+    }
+
+    /// Blob SHA of `path` on the branch, looked up in its parent directory so the file itself
+    /// is never downloaded
+    async fn blob_sha(&self, path: &str) -> StorageResult<Option<String>> {
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let Some(items) = self.directory(parent).await? else {
+            return Ok(None);
+        };
+
+        let found = items
+            .iter()
+            .find(|item| item.path == path && item.r#type == "file");
+        if let Some(item) = found {
+            return Ok(Some(item.sha.clone()));
+        }
+
+        // The Contents API lists at most 1000 entries per directory
+        if items.len() < CONTENTS_LIMIT {
+            return Ok(None);
+        }
+        let sha = self
+            .tree_entries()
+            .await?
+            .into_iter()
+            .find(|entry| entry.path == path && entry.kind == "blob")
+            .map(|entry| entry.sha);
+
+        Ok(sha)
+    }
+
+    async fn create_file(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+        let commit = self
+            .signed(self.client.repos(&self.owner, &self.repo).create_file(
+                path,
+                format!("Create {}", path),
+                content,
+            ))
             .send()
             .await
             .map_err(|e| {
@@ -100,6 +567,168 @@ impl GitHubStorage {
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct GitObject {
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitRef {
+    object: GitObject,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitCommit {
+    tree: GitObject,
+}
+
+/// Files of a compare-commits response; GitHub lists at most [`MAX_COMPARE_FILES`]
+#[derive(Debug, Deserialize)]
+struct Comparison {
+    status: String,
+    #[serde(default)]
+    files: Vec<ComparedFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComparedFile {
+    filename: String,
+    #[serde(default)]
+    previous_filename: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitTreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    sha: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitTree {
+    tree: Vec<GitTreeEntry>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct NewBlob<'a> {
+    content: &'a str,
+    encoding: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct NewTreeEntry<'a> {
+    path: &'a str,
+    mode: &'a str,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    sha: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NewTree<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_tree: Option<&'a str>,
+    tree: Vec<NewTreeEntry<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct NewCommit<'a> {
+    message: &'a str,
+    tree: &'a str,
+    parents: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<&'a CommitAuthor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committer: Option<&'a CommitAuthor>,
+}
+
+#[derive(Debug, Serialize)]
+struct NewRepository<'a> {
+    name: &'a str,
+    private: bool,
+    auto_init: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct UpdateRef<'a> {
+    sha: &'a str,
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct NewRef<'a> {
+    r#ref: String,
+    sha: &'a str,
+}
+
+/// Maximum number of entries the Contents API returns for a directory
+const CONTENTS_LIMIT: usize = 1000;
+
+/// Whether GitHub rejected a request because the state it was based on is outdated
+fn is_conflict(err: &octocrab::Error) -> bool {
+    matches!(
+        err,
+        octocrab::Error::GitHub { source, .. }
+            if source.status_code == http::StatusCode::CONFLICT
+                || source.status_code == http::StatusCode::UNPROCESSABLE_ENTITY
+    )
+}
+
+fn api_error(err: octocrab::Error, action: &str) -> Report<StorageError> {
+    let message = format!("Failed to {}: {}", action, err);
+    Report::new(StorageError::GitHub(err.into())).attach_printable(message)
+}
+
+/// `value` with every byte but the unreserved characters of RFC 3986 percent-encoded, for use
+/// as one path segment or query value
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The decoded content of a file returned by the contents API
+fn decode_content(path: &str, item: &Content) -> StorageResult<String> {
+    if let Some(encoded_content) = &item.content {
+        // GitHub API returns base64 encoded content with possible newlines
+        let cleaned_encoded = encoded_content.replace("\n", "");
+
+        // Decode the content
+        let content = general_purpose::STANDARD
+            .decode(&cleaned_encoded)
+            .map_err(|e| {
+                Report::new(StorageError::GitHub(GitHubStorageError::EncodingError))
+                    .attach_printable(format!("Failed to decode content: {}", e))
+            })?;
+
+        let content_str = String::from_utf8(content).map_err(|e| {
+            Report::new(StorageError::GitHub(GitHubStorageError::EncodingError))
+                .attach_printable(format!("Failed to convert bytes to UTF-8: {}", e))
+        })?;
+
+        Ok(content_str)
+    } else {
+        Err(
+            Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
+                "No content found".into(),
+            )))
+            .attach_printable(format!("No content found for path: {}", path)),
+        )
+    }
+}
+
+/// Number of files after which the compare-commits API truncates its list
+const MAX_COMPARE_FILES: usize = 300;
+
 #[async_trait]
 impl StorageBackend for GitHubStorage {
     async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
@@ -271,10 +900,12 @@ impl StorageBackend for GitHubStorage {
 
         // Update the file
         let commit = self
-            .client
-            .repos(&self.owner, &self.repo)
-            .update_file(path, format!("Update {}", path), content, &sha)
-            .branch(&self.branch)
+            .signed(self.client.repos(&self.owner, &self.repo).update_file(
+                path,
+                format!("Update {}", path),
+                content,
+                &sha,
+            ))
             .send()
             .await
             .map_err(|e| {
@@ -306,6 +937,62 @@ impl StorageBackend for GitHubStorage {
         })
     }
 
+    async fn write_if(
+        &self,
+        path: &str,
+        content: &str,
+        expected_sha: Option<&str>,
+    ) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+
+        // The Contents API checks the SHA itself: an update with a stale SHA is rejected with
+        // 409 and a create over an existing file with 422
+        let repos = self.client.repos(&self.owner, &self.repo);
+        let result = match expected_sha {
+            Some(sha) => {
+                self.signed(repos.update_file(path, format!("Update {}", path), content, sha))
+                    .send()
+                    .await
+            }
+            None => {
+                self.signed(repos.create_file(path, format!("Create {}", path), content))
+                    .send()
+                    .await
+            }
+        };
+
+        let update = match result {
+            Ok(update) => update,
+            Err(e) => {
+                if is_conflict(&e) {
+                    let current = self.current_sha(path, &self.branch).await?;
+                    check_sha(path, expected_sha, current.as_deref())?;
+                }
+                return Err(api_error(e, "write file"));
+            }
+        };
+
+        let modified = update
+            .commit
+            .committer
+            .and_then(|committer| committer.date)
+            .ok_or_else(|| {
+                Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
+                    "Missing committer date".into(),
+                )))
+            })?;
+        let created = match expected_sha {
+            Some(_) => self.first_commit_date(path).await?,
+            None => modified,
+        };
+
+        Ok(FileMeta {
+            sha: update.content.sha,
+            created,
+            modified,
+        })
+    }
+
     async fn read(&self, path: &str) -> StorageResult<String> {
         let get_result = self
             .client
@@ -336,32 +1023,116 @@ impl StorageBackend for GitHubStorage {
             )))
         })?;
 
-        if let Some(encoded_content) = &item.content {
-            // GitHub API returns base64 encoded content with possible newlines
-            let cleaned_encoded = encoded_content.replace("\n", "");
-
-            // Decode the content
-            let content = general_purpose::STANDARD
-                .decode(&cleaned_encoded)
-                .map_err(|e| {
-                    Report::new(StorageError::GitHub(GitHubStorageError::EncodingError))
-                        .attach_printable(format!("Failed to decode content: {}", e))
-                })?;
+        decode_content(path, item)
+    }
 
-            let content_str = String::from_utf8(content).map_err(|e| {
-                Report::new(StorageError::GitHub(GitHubStorageError::EncodingError))
-                    .attach_printable(format!("Failed to convert bytes to UTF-8: {}", e))
+    /// Sends the ETag as `If-None-Match`; a `304 Not Modified` answer does not count against
+    /// the rate limit.
+    async fn read_if_modified(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> StorageResult<Revalidation> {
+        validate_path(path)?;
+        let route = format!(
+            "/repos/{}/{}/contents/{}?ref={}",
+            self.owner,
+            self.repo,
+            path.split('/')
+                .map(url_encode)
+                .collect::<Vec<_>>()
+                .join("/"),
+            url_encode(&self.branch)
+        );
+        let mut headers = http::HeaderMap::new();
+        if let Some(etag) = etag {
+            let value = http::HeaderValue::from_str(etag).map_err(|e| {
+                Report::new(StorageError::GitHub(GitHubStorageError::ApiError(
+                    "Invalid ETag".into(),
+                )))
+                .attach_printable(format!("Invalid ETag {:?}: {}", etag, e))
             })?;
+            headers.insert(http::header::IF_NONE_MATCH, value);
+        }
 
-            Ok(content_str)
-        } else {
-            Err(
-                Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
-                    "No content found".into(),
-                )))
-                .attach_printable(format!("No content found for path: {}", path)),
-            )
+        let response = self
+            .client
+            ._get_with_headers(route, Some(headers))
+            .await
+            .map_err(|e| api_error(e, "get content"))?;
+        if response.status() == http::StatusCode::NOT_MODIFIED {
+            return Ok(Revalidation::NotModified);
         }
+
+        let etag = response
+            .headers()
+            .get(http::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let response = match octocrab::map_github_error(response).await {
+            Ok(response) => response,
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::NOT_FOUND =>
+            {
+                return Err(Report::new(StorageError::NotFound(format!(
+                    "File not found: {}",
+                    path
+                ))));
+            }
+            Err(e) => return Err(api_error(e, "get content")),
+        };
+        let body = self
+            .client
+            .body_to_string(response)
+            .await
+            .map_err(|e| api_error(e, "read content"))?;
+        let item: Content = serde_json::from_str(&body).map_err(|e| {
+            Report::new(StorageError::GitHub(GitHubStorageError::MissingData(
+                "Unexpected contents response".into(),
+            )))
+            .attach_printable(format!("Failed to parse content of {}: {}", path, e))
+        })?;
+
+        Ok(Revalidation::Modified {
+            content: decode_content(path, &item)?,
+            etag,
+        })
+    }
+
+    async fn head(&self) -> StorageResult<Option<String>> {
+        self.branch_head().await
+    }
+
+    async fn changed_paths(&self, from: &str, to: &str) -> StorageResult<Option<Vec<String>>> {
+        let route = format!(
+            "/repos/{}/{}/compare/{}...{}",
+            self.owner, self.repo, from, to
+        );
+        let comparison: Comparison = match self.client.get(route, None::<&()>).await {
+            Ok(comparison) => comparison,
+            // `from` is gone after a force push
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::NOT_FOUND =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(api_error(e, "compare commits")),
+        };
+
+        // Unless `to` descends from `from` the files are relative to their merge base, and past
+        // the limit the list is incomplete
+        if !matches!(comparison.status.as_str(), "ahead" | "identical")
+            || comparison.files.len() >= MAX_COMPARE_FILES
+        {
+            return Ok(None);
+        }
+
+        let paths = comparison
+            .files
+            .into_iter()
+            .flat_map(|file| std::iter::once(file.filename).chain(file.previous_filename))
+            .collect();
+        Ok(Some(paths))
     }
 
     async fn delete(&self, path: &str) -> StorageResult<()> {
@@ -393,21 +1164,212 @@ impl StorageBackend for GitHubStorage {
             )))
         })?;
 
-        self.client
-            .repos(&self.owner, &self.repo)
-            .delete_file(path, format!("Delete {}", path), &item.sha)
-            .branch(&self.branch)
-            .send()
-            .await
-            .map_err(|e| {
-                Report::new(StorageError::GitHub(GitHubStorageError::ApiError(
-                    "API error".into(),
-                )))
-                .attach_printable(format!("Failed to delete file: {}", e))
-            })?;
+        self.signed_delete(self.client.repos(&self.owner, &self.repo).delete_file(
+            path,
+            format!("Delete {}", path),
+            &item.sha,
+        ))
+        .send()
+        .await
+        .map_err(|e| {
+            Report::new(StorageError::GitHub(GitHubStorageError::ApiError(
+                "API error".into(),
+            )))
+            .attach_printable(format!("Failed to delete file: {}", e))
+        })?;
 
         Ok(())
     }
+
+    async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+
+        let sha = self.blob_sha(path).await?.ok_or_else(|| {
+            Report::new(StorageError::NotFound(format!("File not found: {}", path)))
+        })?;
+
+        Ok(FileMeta {
+            sha,
+            created: self.first_commit_date(path).await?,
+            modified: self.last_commit_date(path).await?,
+        })
+    }
+
+    async fn sha(&self, path: &str) -> StorageResult<Option<String>> {
+        validate_path(path)?;
+        self.blob_sha(path).await
+    }
+
+    /// One directory listing, where `stat` would also look up commit dates
+    async fn exists(&self, path: &str) -> StorageResult<bool> {
+        Ok(self.sha(path).await?.is_some())
+    }
+
+    async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+        let prefix = prefix.trim_end_matches('/');
+
+        let entries = if options.recursive {
+            self.tree_listing(prefix, true).await?
+        } else {
+            let items = self.directory(prefix).await?.unwrap_or_default();
+            if items.len() >= CONTENTS_LIMIT {
+                // Too large for the Contents API, derive the children from the full tree
+                self.tree_listing(prefix, false).await?
+            } else {
+                items
+                    .into_iter()
+                    .filter_map(|item| match item.r#type.as_str() {
+                        "file" => Some(Entry::file(item.path)),
+                        "dir" => Some(Entry::dir(item.path)),
+                        _ => None,
+                    })
+                    .collect()
+            }
+        };
+
+        Ok(paginate(entries, options))
+    }
+
+    async fn commit(&self, message: &str, changes: &[Change]) -> StorageResult<Option<String>> {
+        for change in changes {
+            validate_path(change.path())?;
+        }
+
+        self.commit_changes(message, changes).await.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::fake_github::{self, FakeGitHub};
+
+    #[tokio::test]
+    async fn test_read_if_modified_encodes_route() {
+        let path = "notes/a b#1%?.json";
+        let github = FakeGitHub::start(move |request| match request.path.as_str() {
+            "/repos/octo/notes/contents/notes/a%20b%231%25%3F.json?ref=feature%2Fx" => {
+                fake_github::file(path, "hello")
+            }
+            _ => fake_github::not_found(),
+        })
+        .await;
+        let storage = github.storage("octo", "notes").with_branch("feature/x");
+
+        let revalidation = storage.read_if_modified(path, None).await.unwrap();
+        assert!(matches!(
+            revalidation,
+            Revalidation::Modified { content, .. } if content == "hello"
+        ));
+
+        let err = storage
+            .read_if_modified("../secret", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::InvalidPath(_)
+        ));
+        assert_eq!(github.requests().len(), 1);
+    }
+
+    /// A repository on `main` at commit `c0` holding only `notes/a.json`
+    fn committing(request: &fake_github::Request) -> (u16, String) {
+        let path = request.path.strip_prefix("/repos/octo/notes").unwrap_or("");
+        match (request.method.as_str(), path) {
+            ("GET", "/git/ref/heads/main") => (200, r#"{"object": {"sha": "c0"}}"#.into()),
+            ("GET", "/git/commits/c0") => (200, r#"{"tree": {"sha": "t0"}}"#.into()),
+            ("GET", "/contents/notes/a.json?ref=c0") => fake_github::file("notes/a.json", "1"),
+            ("POST", "/git/blobs" | "/git/trees" | "/git/commits") => {
+                (201, r#"{"sha": "c1"}"#.into())
+            }
+            ("PATCH", "/git/refs/heads/main") => (200, r#"{"object": {"sha": "c1"}}"#.into()),
+            _ => fake_github::not_found(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_collapses_changes_per_path() {
+        let github = FakeGitHub::start(committing).await;
+        let storage = github.storage("octo", "notes");
+
+        storage
+            .commit(
+                "Batch",
+                &[
+                    Change::write("notes/a.json", "2"),
+                    Change::delete("notes/a.json"),
+                    Change::write("notes/b.json", "3"),
+                    Change::write("notes/b.json", "4"),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let requests = github.requests();
+        let posted = |endpoint: &str| {
+            requests
+                .iter()
+                .filter(|request| request.method == "POST" && request.path.ends_with(endpoint))
+                .map(|request| serde_json::from_str(&request.body).unwrap())
+                .collect::<Vec<serde_json::Value>>()
+        };
+        assert_eq!(
+            posted("/git/blobs"),
+            vec![serde_json::json!({"content": "4", "encoding": "utf-8"})]
+        );
+        let trees = posted("/git/trees");
+        assert_eq!(
+            trees[0]["tree"],
+            serde_json::json!([
+                {"path": "notes/a.json", "mode": "100644", "type": "blob", "sha": null},
+                {"path": "notes/b.json", "mode": "100644", "type": "blob", "sha": "c1"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_deleting_missing_path() {
+        let github = FakeGitHub::start(committing).await;
+        let storage = github.storage("octo", "notes");
+
+        let err = storage
+            .commit(
+                "Batch",
+                &[
+                    Change::write("notes/b.json", "1"),
+                    Change::delete("notes/missing.json"),
+                ],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+        assert!(github
+            .requests()
+            .iter()
+            .all(|request| request.method == "GET"));
+    }
+
+    #[tokio::test]
+    async fn test_sha_lists_the_parent_directory() {
+        let github = FakeGitHub::start(|request| match request.path.as_str() {
+            "/repos/octo/notes/contents/notes?ref=main" => {
+                fake_github::directory(&[("notes/a.json", "1")])
+            }
+            _ => fake_github::not_found(),
+        })
+        .await;
+        let storage = github.storage("octo", "notes");
+
+        let sha = storage.sha("notes/a.json").await.unwrap();
+        assert_eq!(sha, Some(git_blob_sha(b"1")));
+        assert_eq!(storage.sha("notes/b.json").await.unwrap(), None);
+        assert_eq!(github.requests().len(), 2);
+
+        assert!(storage.exists("notes/a.json").await.unwrap());
+        assert!(!storage.exists("notes").await.unwrap());
+        assert_eq!(github.requests().len(), 4);
+    }
 }
 
 // #[cfg(test)]
@@ -0,0 +1,451 @@
+use crate::error::{StorageError, StorageResult};
+
+use super::{
+    check_sha, git_blob_sha, paginate, validate_path, Change, Entry, FileMeta, ListOptions,
+    ListPage, StorageBackend,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+impl fmt::Display for LocalFsStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LocalFsStorage(root={})", self.root.display())
+    }
+}
+
+/// Storage backend that keeps every file under a root directory on the local filesystem.
+///
+/// Mutations are serialized within the process so conditional writes cannot interleave; other
+/// processes writing the same directory are not coordinated with. Every write replaces the
+/// file, so the creation time reported for it is that of its last write.
+#[derive(Debug, Clone)]
+pub struct LocalFsStorage {
+    root: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl LocalFsStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalFsStorage {
+            root: root.into(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve a repository path to a filesystem path, rejecting anything that escapes the root
+    fn resolve(&self, path: &str) -> StorageResult<PathBuf> {
+        validate_path(path)?;
+
+        Ok(self.root.join(path))
+    }
+
+    /// Remove empty directories left behind by a delete, mirroring Git which does not track them
+    async fn prune_empty_dirs(&self, mut dir: Option<&Path>) {
+        while let Some(current) = dir {
+            if current == self.root || !current.starts_with(&self.root) {
+                break;
+            }
+            // `remove_dir` fails on non-empty directories, which is where we stop
+            if tokio::fs::remove_dir(current).await.is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+}
+
+fn io_error(err: io::Error, path: &str) -> Report<StorageError> {
+    let storage_error = match err.kind() {
+        io::ErrorKind::NotFound => StorageError::NotFound(format!("File not found: {}", path)),
+        io::ErrorKind::PermissionDenied => StorageError::PermissionDenied(path.to_string()),
+        _ => StorageError::IoError(err.to_string()),
+    };
+
+    Report::new(storage_error).attach_printable(format!("I/O failure on path: {}", path))
+}
+
+async fn file_times(full_path: &Path, path: &str) -> StorageResult<(DateTime<Utc>, DateTime<Utc>)> {
+    let metadata = tokio::fs::metadata(full_path)
+        .await
+        .map_err(|e| io_error(e, path))?;
+
+    let modified = metadata.modified().map_err(|e| io_error(e, path))?;
+    // Not every filesystem records a birth time, fall back to the modification time
+    let created = metadata.created().unwrap_or(modified);
+
+    Ok((created.into(), modified.into()))
+}
+
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+impl LocalFsStorage {
+    async fn write_file(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+        let full_path = self.resolve(path)?;
+
+        if let Some(parent) = full_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| io_error(e, path))?;
+        }
+
+        // Write through a temporary file next to the target and rename it into place, so a
+        // failed write never leaves a truncated file behind
+        let temp = full_path.with_extension(format!(
+            "tmp{}-{}",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = tokio::fs::write(&temp, content).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(io_error(e, path));
+        }
+        if let Err(e) = tokio::fs::rename(&temp, &full_path).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(io_error(e, path));
+        }
+
+        let (created, modified) = file_times(&full_path, path).await?;
+
+        Ok(FileMeta {
+            sha: git_blob_sha(content.as_bytes()),
+            created,
+            modified,
+        })
+    }
+
+    async fn delete_file(&self, path: &str) -> StorageResult<()> {
+        let full_path = self.resolve(path)?;
+
+        tokio::fs::remove_file(&full_path)
+            .await
+            .map_err(|e| io_error(e, path))?;
+
+        self.prune_empty_dirs(full_path.parent()).await;
+
+        Ok(())
+    }
+
+    /// Blob SHA of the file currently at `path`, or `None` if there is none
+    async fn current_sha(&self, path: &str) -> StorageResult<Option<String>> {
+        match self.read(path).await {
+            Ok(content) => Ok(Some(git_blob_sha(content.as_bytes()))),
+            Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalFsStorage {
+    async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+        let _guard = self.lock.lock().await;
+        self.write_file(path, content).await
+    }
+
+    async fn write_if(
+        &self,
+        path: &str,
+        content: &str,
+        expected_sha: Option<&str>,
+    ) -> StorageResult<FileMeta> {
+        let _guard = self.lock.lock().await;
+
+        let current = self.current_sha(path).await?;
+        check_sha(path, expected_sha, current.as_deref())?;
+
+        self.write_file(path, content).await
+    }
+
+    async fn read(&self, path: &str) -> StorageResult<String> {
+        let full_path = self.resolve(path)?;
+
+        let content = tokio::fs::read(&full_path)
+            .await
+            .map_err(|e| io_error(e, path))?;
+
+        String::from_utf8(content).map_err(|e| {
+            Report::new(StorageError::IoError("Invalid UTF-8 content".into()))
+                .attach_printable(format!("Failed to convert bytes to UTF-8: {}", e))
+        })
+    }
+
+    async fn delete(&self, path: &str) -> StorageResult<()> {
+        let _guard = self.lock.lock().await;
+        self.delete_file(path).await
+    }
+
+    async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+        let full_path = self.resolve(path)?;
+
+        let content = tokio::fs::read(&full_path)
+            .await
+            .map_err(|e| io_error(e, path))?;
+        let (created, modified) = file_times(&full_path, path).await?;
+
+        Ok(FileMeta {
+            sha: git_blob_sha(&content),
+            created,
+            modified,
+        })
+    }
+
+    async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+        let prefix = prefix.trim_end_matches('/');
+        let start = if prefix.is_empty() {
+            self.root.clone()
+        } else {
+            self.resolve(prefix)?
+        };
+
+        let mut found = Vec::new();
+        let mut pending = vec![start];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(io_error(e, prefix)),
+            };
+
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| io_error(e, prefix))?
+            {
+                let file_type = entry.file_type().await.map_err(|e| io_error(e, prefix))?;
+                let Ok(relative) = entry.path().strip_prefix(&self.root).map(Path::to_path_buf)
+                else {
+                    continue;
+                };
+                let parts: Vec<_> = relative.iter().map(|p| p.to_string_lossy()).collect();
+                let path = parts.join("/");
+
+                if file_type.is_dir() && options.recursive {
+                    pending.push(entry.path());
+                } else if file_type.is_dir() {
+                    found.push(Entry::dir(path));
+                } else if file_type.is_file() {
+                    found.push(Entry::file(path));
+                }
+            }
+        }
+
+        Ok(paginate(found, options))
+    }
+
+    /// Preconditions are all checked before any file is touched and each file is replaced
+    /// whole, but a failure half way through (e.g. a full disk) can still leave part of the
+    /// batch applied
+    async fn commit(&self, _message: &str, changes: &[Change]) -> StorageResult<Option<String>> {
+        for change in changes {
+            validate_path(change.path())?;
+        }
+
+        let _guard = self.lock.lock().await;
+
+        // Check every precondition against the state the batch will have reached, before
+        // touching anything
+        let mut shas: HashMap<&str, Option<String>> = HashMap::new();
+        for change in changes {
+            let path = change.path();
+            let sha = match shas.get_mut(path) {
+                Some(sha) => sha,
+                None => shas.entry(path).or_insert(self.current_sha(path).await?),
+            };
+            if let Some(expected) = change.expected_sha() {
+                check_sha(path, Some(expected), sha.as_deref())?;
+            }
+
+            *sha = match change {
+                Change::Write { content, .. } => Some(git_blob_sha(content.as_bytes())),
+                Change::Delete { .. } if sha.is_some() => None,
+                Change::Delete { .. } => {
+                    return Err(Report::new(StorageError::NotFound(format!(
+                        "File not found: {}",
+                        path
+                    ))));
+                }
+            };
+        }
+
+        for change in changes {
+            match change {
+                Change::Write { path, content, .. } => {
+                    self.write_file(path, content).await?;
+                }
+                Change::Delete { path, .. } => self.delete_file(path).await?,
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_read_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalFsStorage::new(dir.path());
+
+        let meta = storage
+            .write("collections/notes/doc1.json", "hello world\n")
+            .await
+            .unwrap();
+        assert_eq!(meta.sha, "3b18e512dba79e4c8300dd08aeb37f8e728b8dad");
+        assert!(meta.created <= meta.modified);
+
+        let content = storage.read("collections/notes/doc1.json").await.unwrap();
+        assert_eq!(content, "hello world\n");
+
+        storage.delete("collections/notes/doc1.json").await.unwrap();
+        assert!(!dir.path().join("collections").exists());
+
+        let err = storage
+            .read("collections/notes/doc1.json")
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn test_write_if() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalFsStorage::new(dir.path());
+
+        let meta = storage.write_if("a.json", "1", None).await.unwrap();
+        let err = storage.write_if("a.json", "1", None).await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { .. }
+        ));
+
+        storage
+            .write_if("a.json", "2", Some(&meta.sha))
+            .await
+            .unwrap();
+        let err = storage
+            .write_if("a.json", "3", Some(&meta.sha))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { .. }
+        ));
+        assert_eq!(storage.read("a.json").await.unwrap(), "2");
+    }
+
+    #[tokio::test]
+    async fn test_list_and_stat() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalFsStorage::new(dir.path());
+        for path in ["notes/a.json", "notes/b.json", "notes/_indexes/by_tag.json"] {
+            storage.write(path, "{}").await.unwrap();
+        }
+
+        let page = storage
+            .list("notes", &ListOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            page.entries,
+            vec![
+                Entry::dir("notes/_indexes"),
+                Entry::file("notes/a.json"),
+                Entry::file("notes/b.json")
+            ]
+        );
+
+        let page = storage
+            .list("notes", &ListOptions::recursive().limit(2))
+            .await
+            .unwrap();
+        assert_eq!(page.next_cursor.as_deref(), Some("notes/a.json"));
+        assert_eq!(storage.list_files("").await.unwrap().len(), 3);
+
+        let meta = storage.stat("notes/a.json").await.unwrap();
+        assert_eq!(meta.sha, git_blob_sha(b"{}"));
+        assert!(!storage.exists("notes/c.json").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalFsStorage::new(dir.path());
+        storage.write("notes/a.json", "1").await.unwrap();
+
+        // Deleting a missing file fails the batch before the write ahead of it lands
+        let err = storage
+            .commit(
+                "batch",
+                &[
+                    Change::write("notes/b.json", "2"),
+                    Change::delete("notes/c.json"),
+                ],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+        assert!(!storage.exists("notes/b.json").await.unwrap());
+
+        // A file written earlier in the batch can be deleted, but not twice
+        let err = storage
+            .commit(
+                "batch",
+                &[
+                    Change::delete("notes/a.json"),
+                    Change::delete("notes/a.json"),
+                ],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+        storage
+            .commit(
+                "batch",
+                &[
+                    Change::write("notes/b.json", "2"),
+                    Change::delete("notes/b.json"),
+                    Change::write("notes/a.json", "3").expecting(git_blob_sha(b"1")),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.read("notes/a.json").await.unwrap(), "3");
+        assert!(!storage.exists("notes/b.json").await.unwrap());
+
+        // Writes go through a temporary file that does not outlive them
+        let names: Vec<_> = std::fs::read_dir(dir.path().join("notes"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["a.json"]);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_escaping_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalFsStorage::new(dir.path());
+
+        for path in ["", "../outside.json", "/etc/passwd", "./doc.json"] {
+            let err = storage.write(path, "{}").await.unwrap_err();
+            assert!(matches!(
+                err.current_context(),
+                StorageError::InvalidPath(_)
+            ));
+        }
+    }
+}
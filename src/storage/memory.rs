@@ -0,0 +1,348 @@
+use crate::error::{StorageError, StorageResult};
+
+use super::{
+    check_sha, entries_below, git_blob_sha, paginate, validate_path, Change, FileMeta, ListOptions,
+    ListPage, StorageBackend,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+impl fmt::Display for InMemoryStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InMemoryStorage")
+    }
+}
+
+/// A single revision of a path; `content` is `None` when the revision deleted the file
+#[derive(Debug, Clone)]
+pub struct FileVersion {
+    pub sha: Option<String>,
+    pub content: Option<String>,
+    pub modified: DateTime<Utc>,
+}
+
+/// Storage backend that keeps every file and its full revision history in memory.
+///
+/// Cloning the storage shares the underlying map, so a test can hand one clone to
+/// `GitBase` and inspect the other.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryStorage {
+    files: Arc<RwLock<HashMap<String, Vec<FileVersion>>>>,
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All revisions of `path`, oldest first, including deletions
+    pub async fn history(&self, path: &str) -> Vec<FileVersion> {
+        self.files
+            .read()
+            .await
+            .get(path)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Paths that currently hold content, in no particular order
+    pub async fn paths(&self) -> Vec<String> {
+        self.files
+            .read()
+            .await
+            .iter()
+            .filter(|(_, versions)| current(versions).is_some())
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+/// The live revision of a path, if the last revision did not delete it
+fn current(versions: &[FileVersion]) -> Option<&FileVersion> {
+    versions.last().filter(|version| version.content.is_some())
+}
+
+/// When the file in its current incarnation was first written
+fn created_at(versions: &[FileVersion]) -> Option<DateTime<Utc>> {
+    versions
+        .iter()
+        .rev()
+        .take_while(|version| version.content.is_some())
+        .last()
+        .map(|version| version.modified)
+}
+
+type Files = HashMap<String, Vec<FileVersion>>;
+
+fn not_found(path: &str) -> Report<StorageError> {
+    Report::new(StorageError::NotFound(format!("File not found: {}", path)))
+}
+
+fn current_sha<'f>(files: &'f Files, path: &str) -> Option<&'f str> {
+    files
+        .get(path)
+        .and_then(|versions| current(versions))
+        .and_then(|version| version.sha.as_deref())
+}
+
+fn apply_write(files: &mut Files, path: &str, content: &str, now: DateTime<Utc>) -> FileMeta {
+    let versions = files.entry(path.to_string()).or_default();
+    let sha = git_blob_sha(content.as_bytes());
+
+    // Writing identical content is a no-op, just like committing an unchanged file
+    let unchanged = current(versions).is_some_and(|v| v.sha.as_deref() == Some(&sha));
+    if !unchanged {
+        versions.push(FileVersion {
+            sha: Some(sha.clone()),
+            content: Some(content.to_string()),
+            modified: now,
+        });
+    }
+
+    let modified = versions[versions.len() - 1].modified;
+    let created = created_at(versions).unwrap_or(modified);
+
+    FileMeta {
+        sha,
+        created,
+        modified,
+    }
+}
+
+fn apply_delete(files: &mut Files, path: &str, now: DateTime<Utc>) -> StorageResult<()> {
+    let versions = files
+        .get_mut(path)
+        .filter(|versions| current(versions).is_some())
+        .ok_or_else(|| not_found(path))?;
+
+    versions.push(FileVersion {
+        sha: None,
+        content: None,
+        modified: now,
+    });
+
+    Ok(())
+}
+
+#[async_trait]
+impl StorageBackend for InMemoryStorage {
+    async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+
+        let mut files = self.files.write().await;
+        Ok(apply_write(&mut files, path, content, Utc::now()))
+    }
+
+    async fn write_if(
+        &self,
+        path: &str,
+        content: &str,
+        expected_sha: Option<&str>,
+    ) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+
+        let mut files = self.files.write().await;
+        check_sha(path, expected_sha, current_sha(&files, path))?;
+        Ok(apply_write(&mut files, path, content, Utc::now()))
+    }
+
+    async fn read(&self, path: &str) -> StorageResult<String> {
+        validate_path(path)?;
+
+        let files = self.files.read().await;
+        files
+            .get(path)
+            .and_then(|versions| current(versions))
+            .and_then(|version| version.content.clone())
+            .ok_or_else(|| not_found(path))
+    }
+
+    async fn delete(&self, path: &str) -> StorageResult<()> {
+        validate_path(path)?;
+
+        let mut files = self.files.write().await;
+        apply_delete(&mut files, path, Utc::now())
+    }
+
+    async fn stat(&self, path: &str) -> StorageResult<FileMeta> {
+        validate_path(path)?;
+
+        let files = self.files.read().await;
+        let versions = files.get(path).ok_or_else(|| not_found(path))?;
+        let version = current(versions).ok_or_else(|| not_found(path))?;
+
+        Ok(FileMeta {
+            sha: version.sha.clone().unwrap_or_default(),
+            created: created_at(versions).unwrap_or(version.modified),
+            modified: version.modified,
+        })
+    }
+
+    async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage> {
+        let paths = self.paths().await;
+        let entries = entries_below(paths.iter().map(String::as_str), prefix, options.recursive);
+
+        Ok(paginate(entries, options))
+    }
+
+    async fn commit(&self, _message: &str, changes: &[Change]) -> StorageResult<Option<String>> {
+        for change in changes {
+            validate_path(change.path())?;
+        }
+
+        let mut files = self.files.write().await;
+
+        // Check every precondition against the state the batch will have reached, before
+        // touching anything
+        let mut shas: HashMap<&str, Option<String>> = HashMap::new();
+        for change in changes {
+            let path = change.path();
+            let sha = shas
+                .entry(path)
+                .or_insert_with(|| current_sha(&files, path).map(str::to_string));
+            if let Some(expected) = change.expected_sha() {
+                check_sha(path, Some(expected), sha.as_deref())?;
+            }
+
+            *sha = match change {
+                Change::Write { content, .. } => Some(git_blob_sha(content.as_bytes())),
+                Change::Delete { .. } if sha.is_some() => None,
+                Change::Delete { .. } => return Err(not_found(path)),
+            };
+        }
+
+        // All revisions of one batch share a timestamp, like files in a single commit
+        let now = Utc::now();
+        for change in changes {
+            match change {
+                Change::Write { path, content, .. } => {
+                    apply_write(&mut files, path, content, now);
+                }
+                Change::Delete { path, .. } => apply_delete(&mut files, path, now)?,
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_versions_and_shas() {
+        let storage = InMemoryStorage::new();
+
+        let first = storage
+            .write("notes/a.json", "hello world\n")
+            .await
+            .unwrap();
+        assert_eq!(first.sha, "3b18e512dba79e4c8300dd08aeb37f8e728b8dad");
+
+        // Same content does not produce a new revision
+        storage
+            .write("notes/a.json", "hello world\n")
+            .await
+            .unwrap();
+        let second = storage.write("notes/a.json", "bye\n").await.unwrap();
+        assert_eq!(second.created, first.created);
+        assert_eq!(storage.history("notes/a.json").await.len(), 2);
+
+        let meta = storage.stat("notes/a.json").await.unwrap();
+        assert_eq!(meta.sha, second.sha);
+        assert_eq!(meta.created, first.created);
+
+        storage.delete("notes/a.json").await.unwrap();
+        let err = storage.read("notes/a.json").await.unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+        assert!(storage.paths().await.is_empty());
+        assert!(!storage.exists("notes/a.json").await.unwrap());
+
+        let history = storage.history("notes/a.json").await;
+        assert_eq!(history.len(), 3);
+        assert!(history[2].content.is_none());
+
+        // A recreated file starts a new lifetime
+        let third = storage.write("notes/a.json", "again\n").await.unwrap();
+        assert!(third.created >= history[2].modified);
+    }
+
+    #[tokio::test]
+    async fn test_commit_is_all_or_nothing() {
+        let storage = InMemoryStorage::new();
+        storage.write("a.json", "a").await.unwrap();
+
+        let err = storage
+            .commit(
+                "Broken batch",
+                &[Change::write("b.json", "b"), Change::delete("missing.json")],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+        assert!(storage.history("b.json").await.is_empty());
+
+        storage
+            .commit(
+                "Swap files",
+                &[Change::write("b.json", "b"), Change::delete("a.json")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.paths().await, vec!["b.json".to_string()]);
+        assert_eq!(
+            storage.history("b.json").await[0].modified,
+            storage.history("a.json").await[1].modified
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_if_conflict() {
+        let storage = InMemoryStorage::new();
+
+        let created = storage.write_if("a.json", "1", None).await.unwrap();
+        let err = storage.write_if("a.json", "2", None).await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { current: Some(sha), .. } if *sha == created.sha
+        ));
+
+        let updated = storage
+            .write_if("a.json", "2", Some(&created.sha))
+            .await
+            .unwrap();
+        // The second writer still holds the old SHA and must not overwrite
+        let err = storage
+            .write_if("a.json", "3", Some(&created.sha))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { current: Some(sha), .. } if *sha == updated.sha
+        ));
+        assert_eq!(storage.read("a.json").await.unwrap(), "2");
+
+        let err = storage
+            .commit("Stale", &[Change::delete("a.json").expecting(created.sha)])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            StorageError::Conflict { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_missing() {
+        let storage = InMemoryStorage::new();
+
+        let err = storage.delete("missing.json").await.unwrap_err();
+        assert!(matches!(err.current_context(), StorageError::NotFound(_)));
+    }
+}
@@ -1,9 +1,27 @@
+mod cached;
+#[cfg(test)]
+pub(crate) mod fake_github;
+mod git;
 mod github;
+mod local;
+mod memory;
 
 use async_trait::async_trait;
 use chrono::DateTime;
+use error_stack::Report;
+use sha1::{Digest, Sha1};
+use std::path::{Component, Path};
 
-use crate::error::StorageResult;
+use crate::error::{StorageError, StorageResult};
+
+pub use cached::{CachedStorage, WritePolicy};
+pub use git::LocalGitStorage;
+pub use github::GitHubStorage;
+pub use local::LocalFsStorage;
+pub use memory::{FileVersion, InMemoryStorage};
+
+/// Branch the Git backends use when none is given
+pub const DEFAULT_BRANCH: &str = "main";
 
 #[derive(Debug)]
 pub struct FileMeta {
@@ -12,11 +30,419 @@ pub struct FileMeta {
     pub modified: DateTime<chrono::Utc>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+/// A file or directory returned by [`StorageBackend::list`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: String,
+    pub kind: EntryKind,
+}
+
+impl Entry {
+    pub fn file(path: impl Into<String>) -> Self {
+        Entry {
+            path: path.into(),
+            kind: EntryKind::File,
+        }
+    }
+
+    pub fn dir(path: impl Into<String>) -> Self {
+        Entry {
+            path: path.into(),
+            kind: EntryKind::Dir,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// List every file below the prefix instead of its direct children
+    pub recursive: bool,
+    /// Maximum number of entries in a page, unlimited when `None`
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+impl ListOptions {
+    pub fn recursive() -> Self {
+        ListOptions {
+            recursive: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn after(mut self, cursor: impl Into<String>) -> Self {
+        self.cursor = Some(cursor.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListPage {
+    pub entries: Vec<Entry>,
+    /// Pass to [`ListOptions::after`] to fetch the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// A single file change staged for a batch commit.
+///
+/// `expected_sha` is an optional precondition: the change only applies if the path currently
+/// holds a blob with that SHA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Write {
+        path: String,
+        content: String,
+        expected_sha: Option<String>,
+    },
+    Delete {
+        path: String,
+        expected_sha: Option<String>,
+    },
+}
+
+impl Change {
+    pub fn write(path: impl Into<String>, content: impl Into<String>) -> Self {
+        Change::Write {
+            path: path.into(),
+            content: content.into(),
+            expected_sha: None,
+        }
+    }
+
+    pub fn delete(path: impl Into<String>) -> Self {
+        Change::Delete {
+            path: path.into(),
+            expected_sha: None,
+        }
+    }
+
+    /// Only apply this change if the path's current blob SHA is `sha`
+    pub fn expecting(mut self, sha: impl Into<String>) -> Self {
+        match &mut self {
+            Change::Write { expected_sha, .. } | Change::Delete { expected_sha, .. } => {
+                *expected_sha = Some(sha.into())
+            }
+        }
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Write { path, .. } | Change::Delete { path, .. } => path,
+        }
+    }
+
+    pub fn expected_sha(&self) -> Option<&str> {
+        match self {
+            Change::Write { expected_sha, .. } | Change::Delete { expected_sha, .. } => {
+                expected_sha.as_deref()
+            }
+        }
+    }
+}
+
+/// Outcome of [`StorageBackend::read_if_modified`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revalidation {
+    /// The file is still at the version the ETag names
+    NotModified,
+    /// The current content, with the ETag naming this version if the backend has one
+    Modified {
+        content: String,
+        etag: Option<String>,
+    },
+}
+
 #[async_trait]
-pub trait StorageBackend {
+pub trait StorageBackend: Send + Sync {
     async fn write(&self, path: &str, content: &str) -> StorageResult<FileMeta>;
 
+    /// Write `content` only if the current blob SHA of `path` is `expected_sha`, or if the path
+    /// does not exist when `expected_sha` is `None`. Fails with [`StorageError::Conflict`]
+    /// carrying the current SHA otherwise.
+    async fn write_if(
+        &self,
+        path: &str,
+        content: &str,
+        expected_sha: Option<&str>,
+    ) -> StorageResult<FileMeta>;
+
     async fn read(&self, path: &str) -> StorageResult<String>;
 
+    /// Read `path` unless it is still at the version `etag` names, as returned by an earlier
+    /// call. The default always reads and returns no ETag.
+    async fn read_if_modified(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> StorageResult<Revalidation> {
+        let _ = etag;
+        Ok(Revalidation::Modified {
+            content: self.read(path).await?,
+            etag: None,
+        })
+    }
+
     async fn delete(&self, path: &str) -> StorageResult<()>;
+
+    /// Metadata of the file at `path` without fetching its content. Fails with
+    /// [`StorageError::NotFound`] if there is no such file.
+    async fn stat(&self, path: &str) -> StorageResult<FileMeta>;
+
+    /// One page of the entries below the directory `prefix`, sorted by path. Without
+    /// `options.recursive` these are the direct children, files and directories; with it every
+    /// file below `prefix`. An empty prefix lists the repository root and a missing directory
+    /// lists nothing.
+    async fn list(&self, prefix: &str, options: &ListOptions) -> StorageResult<ListPage>;
+
+    /// Blob SHA of the file at `path`, or `None` if there is no such file. Backends override it
+    /// when [`Self::stat`] has to work out more than the SHA, such as commit times.
+    async fn sha(&self, path: &str) -> StorageResult<Option<String>> {
+        match self.stat(path).await {
+            Ok(meta) => Ok(Some(meta.sha)),
+            Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Paths of all files below the directory `prefix`, following every page
+    async fn list_files(&self, prefix: &str) -> StorageResult<Vec<String>> {
+        let mut options = ListOptions::recursive();
+        let mut paths = Vec::new();
+        loop {
+            let page = self.list(prefix, &options).await?;
+            paths.extend(page.entries.into_iter().map(|entry| entry.path));
+            match page.next_cursor {
+                Some(cursor) => options.cursor = Some(cursor),
+                None => return Ok(paths),
+            }
+        }
+    }
+
+    async fn exists(&self, path: &str) -> StorageResult<bool> {
+        match self.stat(path).await {
+            Ok(_) => Ok(true),
+            Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The commit the branch currently points at, or `None` if the backend does not record
+    /// commits or the branch has none yet
+    async fn head(&self) -> StorageResult<Option<String>> {
+        Ok(None)
+    }
+
+    /// Paths added, modified or deleted between the commits `from` and `to`, both sides of a
+    /// rename included, or `None` if the backend cannot tell, e.g. because history was rewritten
+    async fn changed_paths(&self, from: &str, to: &str) -> StorageResult<Option<Vec<String>>> {
+        let _ = (from, to);
+        Ok(None)
+    }
+
+    /// Apply `changes` in order as one commit and return its ID, if the backend records commits.
+    ///
+    /// A change whose `expected_sha` does not match fails the batch with
+    /// [`StorageError::Conflict`]. The default checks every precondition up front, then applies
+    /// each change on its own and is not atomic; backends that can land a batch all-or-nothing
+    /// override it.
+    async fn commit(&self, message: &str, changes: &[Change]) -> StorageResult<Option<String>> {
+        let _ = message;
+        for change in changes {
+            if let Some(expected) = change.expected_sha() {
+                let current = match self.stat(change.path()).await {
+                    Ok(meta) => Some(meta.sha),
+                    Err(e) if matches!(e.current_context(), StorageError::NotFound(_)) => None,
+                    Err(e) => return Err(e),
+                };
+                check_sha(change.path(), Some(expected), current.as_deref())?;
+            }
+        }
+
+        for change in changes {
+            match change {
+                Change::Write { path, content, .. } => {
+                    self.write(path, content).await?;
+                }
+                Change::Delete { path, .. } => self.delete(path).await?,
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Whether `path` lies below the directory `prefix`
+pub(crate) fn under_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    prefix.is_empty()
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Turn the file paths of a backend into the entries `list` returns for `prefix`: every file
+/// below it when recursive, otherwise its direct children with deeper files folded into their
+/// top-level directory
+pub(crate) fn entries_below<'p>(
+    paths: impl IntoIterator<Item = &'p str>,
+    prefix: &str,
+    recursive: bool,
+) -> Vec<Entry> {
+    let prefix = prefix.trim_end_matches('/');
+    let mut entries: Vec<Entry> = Vec::new();
+    for path in paths {
+        if !under_prefix(path, prefix) {
+            continue;
+        }
+        if recursive {
+            entries.push(Entry::file(path));
+            continue;
+        }
+
+        let rest = if prefix.is_empty() {
+            path
+        } else {
+            &path[prefix.len() + 1..]
+        };
+        let entry = match rest.split_once('/') {
+            Some((dir, _)) => Entry::dir(&path[..path.len() - rest.len() + dir.len()]),
+            None => Entry::file(path),
+        };
+        entries.push(entry);
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries.dedup();
+    entries
+}
+
+/// Sort `entries` and cut out the page `options` asks for. The cursor is the path of the last
+/// entry of the previous page.
+pub(crate) fn paginate(mut entries: Vec<Entry>, options: &ListOptions) -> ListPage {
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    if let Some(cursor) = &options.cursor {
+        entries.retain(|entry| entry.path.as_str() > cursor.as_str());
+    }
+
+    let next_cursor = match options.limit {
+        Some(limit) if entries.len() > limit => {
+            entries.truncate(limit);
+            entries.last().map(|entry| entry.path.clone())
+        }
+        _ => None,
+    };
+
+    ListPage {
+        entries,
+        next_cursor,
+    }
+}
+
+/// Fail with [`StorageError::Conflict`] unless `current` matches `expected`
+pub(crate) fn check_sha(
+    path: &str,
+    expected: Option<&str>,
+    current: Option<&str>,
+) -> StorageResult<()> {
+    if expected == current {
+        return Ok(());
+    }
+
+    Err(Report::new(StorageError::Conflict {
+        path: path.to_string(),
+        expected: expected.map(str::to_string),
+        current: current.map(str::to_string),
+    }))
+}
+
+/// Compute the Git blob SHA-1 of `content`, identical to `git hash-object`
+pub(crate) fn git_blob_sha(content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()));
+    hasher.update(content);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Check that `path` is a non-empty relative path made only of normal components
+pub(crate) fn validate_path(path: &str) -> StorageResult<()> {
+    if path.is_empty() {
+        return Err(Report::new(StorageError::InvalidPath(
+            "Path cannot be empty".into(),
+        )));
+    }
+
+    let valid = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !valid {
+        return Err(Report::new(StorageError::InvalidPath(path.to_string()))
+            .attach_printable("Path must be relative and must not contain `.` or `..`"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_entries() {
+        let paths = [
+            "a/x.json",
+            "a/b/y.json",
+            "a/b/z.json",
+            "ab/w.json",
+            "top.json",
+        ];
+
+        assert_eq!(
+            entries_below(paths, "a", false),
+            vec![Entry::dir("a/b"), Entry::file("a/x.json")]
+        );
+        assert_eq!(
+            entries_below(paths, "", false),
+            vec![Entry::dir("a"), Entry::dir("ab"), Entry::file("top.json")]
+        );
+        assert_eq!(entries_below(paths, "a/b", true).len(), 2);
+
+        let options = ListOptions::recursive().limit(3);
+        let page = paginate(entries_below(paths, "", true), &options);
+        assert_eq!(page.entries.len(), 3);
+        assert_eq!(page.next_cursor.as_deref(), Some("a/x.json"));
+
+        let options = options.after("a/x.json");
+        let page = paginate(entries_below(paths, "", true), &options);
+        assert_eq!(page.entries[0].path, "ab/w.json");
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn test_git_blob_sha() {
+        // `printf 'hello world\n' | git hash-object --stdin`
+        assert_eq!(
+            git_blob_sha(b"hello world\n"),
+            "3b18e512dba79e4c8300dd08aeb37f8e728b8dad"
+        );
+        assert_eq!(
+            git_blob_sha(b""),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+        );
+    }
 }
@@ -0,0 +1,267 @@
+use error_stack::Report;
+use serde_json::Value;
+
+use crate::document::{document_path, merge_patch, new_document_id, DocumentFile};
+use crate::error::{AppResult, IntoAppResult, StorageError};
+use crate::storage::{git_blob_sha, Change};
+use crate::{Document, GitBase};
+
+/// A set of document changes that lands as a single Git commit.
+///
+/// Nothing is written until [`Transaction::commit`]; dropping the transaction or calling
+/// [`Transaction::rollback`] discards everything staged so far. Reads inside the transaction
+/// see its own staged changes.
+pub struct Transaction<'a> {
+    gitbase: &'a GitBase,
+    changes: Vec<Change>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(gitbase: &'a GitBase) -> Self {
+        Transaction {
+            gitbase,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Stage a new document and return its generated ID
+    pub fn insert(&mut self, collection: &str, content: Value) -> AppResult<String> {
+        let doc_id = new_document_id(&content, self.gitbase.config.id_scheme)?;
+
+        let file = DocumentFile::new(&doc_id, content);
+        self.stage(Change::write(
+            document_path(collection, &doc_id),
+            file.render(self.gitbase.config.document_format)?,
+        ));
+
+        Ok(doc_id)
+    }
+
+    /// Stage a JSON Merge Patch of an existing document's content
+    pub async fn update(&mut self, collection: &str, doc_id: &str, patch: Value) -> AppResult<()> {
+        let path = document_path(collection, doc_id);
+        let (mut file, sha) = self.load(&path).await?;
+
+        merge_patch(&mut file.content, &patch);
+        self.stage_write(path, &file, sha)
+    }
+
+    /// Stage a full replacement of an existing document's content
+    pub async fn replace(
+        &mut self,
+        collection: &str,
+        doc_id: &str,
+        content: Value,
+    ) -> AppResult<()> {
+        let path = document_path(collection, doc_id);
+        let (mut file, sha) = self.load(&path).await?;
+
+        file.content = content;
+        self.stage_write(path, &file, sha)
+    }
+
+    /// Stage writing back a document read earlier. The commit fails with
+    /// `StorageError::Conflict` if it changed since, according to `meta.updated_sha`.
+    pub fn save(&mut self, collection: &str, document: &Document) -> AppResult<()> {
+        let file = DocumentFile {
+            id: document.id.clone(),
+            created_at: document.meta.created_at.clone(),
+            content: document.content.clone(),
+        };
+
+        let path = document_path(collection, &document.id);
+        self.stage_write(path, &file, Some(document.meta.updated_sha.clone()))
+    }
+
+    /// Stage the removal of an existing document
+    pub async fn delete(&mut self, collection: &str, doc_id: &str) -> AppResult<()> {
+        let path = document_path(collection, doc_id);
+        let (_, sha) = self.load(&path).await?;
+
+        let change = Change::delete(path);
+        self.stage(match sha {
+            Some(sha) => change.expecting(sha),
+            None => change,
+        });
+
+        Ok(())
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Write every staged change as one commit with `message`, returning the commit ID when the
+    /// backend records one. Written documents must match the schema of their collection, and
+    /// indexes of the collections touched are updated in the same commit. If the commit fails
+    /// nothing is applied.
+    pub async fn commit(mut self, message: &str) -> AppResult<Option<String>> {
+        if self.changes.is_empty() {
+            return Ok(None);
+        }
+
+        self.gitbase.validate_changes(&self.changes).await?;
+
+        let index_changes = self.gitbase.index_changes(&self.changes).await?;
+        self.changes.extend(index_changes);
+
+        self.gitbase
+            .storage
+            .commit(message, &self.changes)
+            .await
+            .into_app()
+    }
+
+    /// Discard every staged change
+    pub fn rollback(self) {}
+
+    pub(crate) fn stage(&mut self, change: Change) {
+        self.changes.push(change);
+    }
+
+    fn stage_write(
+        &mut self,
+        path: String,
+        file: &DocumentFile,
+        sha: Option<String>,
+    ) -> AppResult<()> {
+        let change = Change::write(path, file.render(self.gitbase.config.document_format)?);
+        self.stage(match sha {
+            Some(sha) => change.expecting(sha),
+            None => change,
+        });
+
+        Ok(())
+    }
+
+    /// The latest version of `path`, as staged in this transaction or stored in the backend.
+    ///
+    /// A document read from the backend comes with its blob SHA, which staged changes carry as
+    /// their precondition so a concurrent writer makes the commit fail instead of being lost.
+    async fn load(&self, path: &str) -> AppResult<(DocumentFile, Option<String>)> {
+        let staged = self
+            .changes
+            .iter()
+            .rev()
+            .find(|change| change.path() == path);
+
+        match staged {
+            Some(Change::Write { content, .. }) => Ok((DocumentFile::parse(content)?, None)),
+            Some(Change::Delete { .. }) => {
+                let err = Report::new(StorageError::NotFound(format!("File not found: {}", path)));
+                Err(err).into_app()
+            }
+            None => {
+                let raw = self.gitbase.storage.read(path).await.into_app()?;
+                let sha = git_blob_sha(raw.as_bytes());
+                Ok((DocumentFile::parse(&raw)?, Some(sha)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::GBError;
+    use crate::storage::{InMemoryStorage, StorageBackend};
+    use serde_json::json;
+
+    fn is_conflict(err: &error_stack::Report<GBError>) -> bool {
+        matches!(
+            err.current_context(),
+            GBError::Storage(StorageError::Conflict { .. })
+        )
+    }
+
+    #[tokio::test]
+    async fn test_document_crud() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+        gitbase.create_database().await.unwrap();
+        gitbase.create_collection("notes").await.unwrap();
+
+        let doc = gitbase
+            .insert_document("notes", json!({"title": "Hello", "tags": ["a"]}))
+            .await
+            .unwrap();
+        let path = document_path("notes", &doc.id);
+        assert_eq!(
+            git_blob_sha(storage.read(&path).await.unwrap().as_bytes()),
+            doc.meta.updated_sha
+        );
+
+        let updated = gitbase
+            .update_document("notes", &doc.id, json!({"title": "Hi", "tags": null}))
+            .await
+            .unwrap();
+        assert_eq!(updated.content, json!({"title": "Hi"}));
+        assert_eq!(updated.meta.created_at, doc.meta.created_at);
+
+        // `doc` is stale now, replacing it must not clobber the update
+        let err = gitbase.replace_document("notes", &doc).await.unwrap_err();
+        assert!(is_conflict(&err));
+
+        let mut fresh = gitbase.get_document("notes", &doc.id).await.unwrap();
+        fresh.content = json!({"title": "Replaced"});
+        gitbase.replace_document("notes", &fresh).await.unwrap();
+
+        gitbase.delete_document("notes", &doc.id).await.unwrap();
+        let err = gitbase.get_document("notes", &doc.id).await.unwrap_err();
+        assert!(matches!(
+            err.current_context(),
+            GBError::Storage(StorageError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_commit_and_rollback() {
+        let storage = InMemoryStorage::new();
+        let gitbase = GitBase::with_storage(storage.clone());
+
+        let mut tx = gitbase.transaction();
+        tx.insert("notes", json!({"title": "a"})).unwrap();
+        tx.rollback();
+        assert!(storage.paths().await.is_empty());
+
+        let mut tx = gitbase.transaction();
+        let note = tx.insert("notes", json!({"title": "a"})).unwrap();
+        let task = tx.insert("tasks", json!({"done": false})).unwrap();
+        // Reads inside the transaction see staged documents
+        tx.update("tasks", &task, json!({"done": true}))
+            .await
+            .unwrap();
+        tx.commit("Add a note and a task").await.unwrap();
+
+        let task = gitbase.get_document("tasks", &task).await.unwrap();
+        assert_eq!(task.content, json!({"done": true}));
+        assert!(gitbase.get_document("notes", &note).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_detects_concurrent_write() {
+        let gitbase = GitBase::with_storage(InMemoryStorage::new());
+        let doc = gitbase
+            .insert_document("notes", json!({"n": 1}))
+            .await
+            .unwrap();
+
+        let mut tx = gitbase.transaction();
+        tx.update("notes", &doc.id, json!({"n": 2})).await.unwrap();
+
+        // Another writer lands first
+        gitbase
+            .update_document("notes", &doc.id, json!({"n": 3}))
+            .await
+            .unwrap();
+
+        let err = tx.commit("Stale update").await.unwrap_err();
+        assert!(is_conflict(&err));
+        let doc = gitbase.get_document("notes", &doc.id).await.unwrap();
+        assert_eq!(doc.content, json!({"n": 3}));
+    }
+}